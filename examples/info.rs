@@ -1,3 +1,18 @@
-fn main() {
-    println!("Hello");
+use package_iter::prelude::*;
+use std::convert::TryFrom;
+use std::env;
+
+/// Print the details of a package from a database file e.g.
+/// `cargo run --example info -- tests/sync/core.db acl`
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .unwrap_or_else(|| "tests/sync/core.db".to_owned());
+    let name = args.next().unwrap_or_else(|| "acl".to_owned());
+
+    let db = Database::default().with_path(path);
+    let repo = RepoIndex::try_from(&db)?;
+    print!("{}", repo.get(&name)?);
+    Ok(())
 }
@@ -0,0 +1,777 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use package_iter::date::{format_rfc3339, DateFormat, Dated};
+use package_iter::prelude::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// `Result<T>` for the commands which fail with library, io or serialization errors
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Query Arch Linux package databases without pacman
+#[derive(Debug, Parser)]
+#[command(name = "package-iter", version)]
+struct Cli {
+    /// Config file listing the repositories [default: /etc/pacman.conf]
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Root directory of the system to inspect e.g. a mounted image
+    #[arg(long, value_name = "DIR", global = true)]
+    root: Option<PathBuf>,
+
+    /// Database directory overriding the config e.g. /var/lib/pacman
+    #[arg(long, value_name = "DIR", global = true)]
+    dbpath: Option<PathBuf>,
+
+    /// Query the given database file instead of the configured repositories
+    #[arg(long = "db", value_name = "FILE", global = true)]
+    dbs: Vec<PathBuf>,
+
+    /// Query the installed packages instead of the sync databases
+    #[arg(long, global = true)]
+    local: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the packages of each repository
+    List {
+        /// Only list the packages of the given repository
+        #[arg(long)]
+        repo: Option<String>,
+    },
+
+    /// Show the details of a package
+    Info { package: String },
+
+    /// Search package names and descriptions with a case insensitive regex, marking
+    /// the installed packages
+    Search {
+        pattern: String,
+
+        /// Field to search, may be repeated [default: name and desc]
+        #[arg(long = "field", value_enum, value_name = "FIELD")]
+        fields: Vec<Field>,
+
+        /// Search every field
+        #[arg(long, conflicts_with = "fields")]
+        all_fields: bool,
+
+        /// Match the case of the pattern
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Match the pattern as plain text instead of a regex
+        #[arg(long)]
+        literal: bool,
+    },
+
+    /// Show the dependencies of a package as a tree
+    Deps {
+        package: String,
+
+        /// List the resolved packages in install order instead of a tree
+        #[arg(long)]
+        flat: bool,
+
+        /// Limit the depth of the tree
+        #[arg(long)]
+        depth: Option<usize>,
+    },
+
+    /// Show the packages requiring a package as a tree
+    Rdeps {
+        package: String,
+
+        /// List every package affected instead of a tree
+        #[arg(long)]
+        flat: bool,
+
+        /// Limit the depth of the tree
+        #[arg(long)]
+        depth: Option<usize>,
+    },
+
+    /// Compare two snapshots of a database e.g. archived copies of core.db
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+
+        /// Print the diff as JSON instead of a changelog
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export the packages in a machine readable format
+    Export {
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+
+        /// Format of dates e.g. the build date
+        #[arg(long, value_enum, default_value_t = Dates::Timestamp)]
+        dates: Dates,
+
+        /// Only export the packages of the given repository
+        #[arg(long)]
+        repo: Option<String>,
+    },
+}
+
+/// `Format` is the output format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+/// `Field` is a package field to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Field {
+    Name,
+    Desc,
+    Provides,
+    Groups,
+}
+impl From<Field> for SearchField {
+    fn from(field: Field) -> Self {
+        match field {
+            Field::Name => SearchField::Name,
+            Field::Desc => SearchField::Desc,
+            Field::Provides => SearchField::Provides,
+            Field::Groups => SearchField::Groups,
+        }
+    }
+}
+
+/// `Dates` is the format of exported dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Dates {
+    Timestamp,
+    Rfc3339,
+}
+impl From<Dates> for DateFormat {
+    fn from(dates: Dates) -> Self {
+        match dates {
+            Dates::Timestamp => DateFormat::Timestamp,
+            Dates::Rfc3339 => DateFormat::Rfc3339,
+        }
+    }
+}
+
+/// `Entry` is an exported package along with its repository
+#[derive(Serialize)]
+struct Entry<'a> {
+    repo: &'a str,
+    #[serde(flatten)]
+    package: &'a Package,
+}
+
+impl Cli {
+    /// Load the config applying the root and database directory overrides. Without a
+    /// config e.g. on a CI image the pacman defaults are used.
+    fn config(&self) -> Result<Config> {
+        let mut config = match self.config {
+            Some(ref path) => Config::load(path)?,
+            None if !Path::new(DEFAULT_CONFIG).exists() => Config::default(),
+            None => Config::load(DEFAULT_CONFIG)?,
+        };
+        if let Some(ref root) = self.root {
+            config = config.with_root(root);
+        }
+        if let Some(ref dir) = self.dbpath {
+            config = config.with_db_path(dir);
+        }
+        Ok(config)
+    }
+
+    /// Load the repositories to query in priority order. Configured databases that
+    /// haven't been synced yet are skipped with a warning.
+    fn repos(&self) -> Result<Vec<RepoIndex>> {
+        if self.local {
+            let local = LocalDatabase::default().with_dir(self.config()?.db_dir());
+            let index = local
+                .packages()?
+                .map(|x| x.map(|x| x.package))
+                .collect::<package_iter::error::Result<RepoIndex>>()?;
+            return Ok(vec![index.with_name("local")]);
+        }
+
+        let databases = if self.dbs.is_empty() {
+            self.config()?.databases()
+        } else {
+            self.dbs
+                .iter()
+                .map(|x| Database::default().with_path(x))
+                .collect()
+        };
+        let mut repos = Vec::new();
+        for db in &databases {
+            if !db.path().is_file() {
+                eprintln!("warning: database not found: {}", db.path().display());
+                continue;
+            }
+            repos.push(RepoIndex::try_from(db)?);
+        }
+        Ok(repos)
+    }
+
+    /// Load the installed packages for the `[installed]` markers of a search. Nothing
+    /// is marked when querying the installed packages themselves or without a local
+    /// database e.g. on a CI image.
+    fn installed(&self) -> Result<Vec<Package>> {
+        let local = LocalDatabase::default().with_dir(self.config()?.db_dir());
+        if self.local || !local.path().is_dir() {
+            return Ok(Vec::new());
+        }
+        let packages = local
+            .packages()?
+            .map(|x| x.map(|x| x.package))
+            .collect::<package_iter::error::Result<_>>()?;
+        Ok(packages)
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = run(&cli, &mut out);
+    if let Err(e) = result.and_then(|_| out.flush().map_err(Into::into)) {
+        // Quietly stop when the output is closed e.g. piped into `head`
+        if broken_pipe(e.as_ref()) {
+            return;
+        }
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Check if the error is from writing to closed output, either directly or while
+/// writing JSON
+fn broken_pipe(e: &(dyn std::error::Error + 'static)) -> bool {
+    let kind = match e.downcast_ref::<io::Error>() {
+        Some(x) => Some(x.kind()),
+        None => e
+            .downcast_ref::<serde_json::Error>()
+            .and_then(|x| x.io_error_kind()),
+    };
+    kind == Some(io::ErrorKind::BrokenPipe)
+}
+
+/// Run the command writing its output
+fn run(cli: &Cli, out: &mut impl Write) -> Result<()> {
+    if let Command::Diff {
+        ref old,
+        ref new,
+        json,
+    } = cli.command
+    {
+        let old = Database::default().with_path(old);
+        let new = Database::default().with_path(new);
+        return diff(out, &DatabaseDiff::load(&old, &new)?, json);
+    }
+
+    let repos = cli.repos()?;
+    match cli.command {
+        Command::List { ref repo } => list(out, &repos, repo.as_deref()),
+        Command::Info { ref package } => info(out, &repos, package),
+        Command::Search {
+            ref pattern,
+            ref fields,
+            all_fields,
+            case_sensitive,
+            literal,
+        } => {
+            let fields = fields
+                .iter()
+                .map(|x| SearchField::from(*x))
+                .collect::<Vec<_>>();
+            let mut search = Search::new(pattern)
+                .with_ignore_case(!case_sensitive)
+                .with_regex(!literal);
+            if all_fields {
+                search = search.with_fields(SEARCH_FIELDS);
+            } else if !fields.is_empty() {
+                search = search.with_fields(&fields);
+            }
+            let formatter = SearchFormatter::default().with_installed(&cli.installed()?);
+            search_output(out, &repos, &search, &formatter)
+        }
+        Command::Deps {
+            ref package,
+            flat,
+            depth,
+        } => deps(out, &repos, package, flat, depth),
+        Command::Rdeps {
+            ref package,
+            flat,
+            depth,
+        } => rdeps(out, &repos, package, flat, depth),
+        Command::Export {
+            format,
+            dates,
+            ref repo,
+        } => export(out, &repos, repo.as_deref(), format, dates.into()),
+        Command::Diff { .. } => unreachable!(),
+    }
+}
+
+/// Write each package as `repo name version` like `pacman -Sl`
+fn list(out: &mut impl Write, repos: &[RepoIndex], repo: Option<&str>) -> Result<()> {
+    for x in filter(repos, repo)? {
+        for pkg in x {
+            writeln!(out, "{} {} {}", x.name(), pkg.name, pkg.version)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the details of the package from each repository that has it like `pacman -Si`
+fn info(out: &mut impl Write, repos: &[RepoIndex], name: &str) -> Result<()> {
+    let reverse = ReverseIndex::new(repos);
+    let found = repos
+        .iter()
+        .filter_map(|repo| repo.get(name).ok().map(|x| (repo.name(), x)))
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        return Err(Error::PackageNotFound(name.to_owned()).into());
+    }
+
+    for (i, (repo, pkg)) in found.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        let field = |out: &mut dyn Write, label: &str, value: &str| {
+            writeln!(out, "{:<16}: {}", label, value)
+        };
+        let list = |values: Vec<String>| {
+            if values.is_empty() {
+                "None".to_owned()
+            } else {
+                values.join("  ")
+            }
+        };
+        field(out, "Repository", repo)?;
+        field(out, "Name", &pkg.name)?;
+        field(out, "Version", pkg.version.as_str())?;
+        field(out, "Description", &pkg.desc)?;
+        field(out, "Architecture", &pkg.arch)?;
+        field(out, "URL", &pkg.url)?;
+        field(out, "Licenses", &list(pkg.licenses.to_owned()))?;
+        field(out, "Groups", &list(pkg.groups.to_owned()))?;
+        field(out, "Provides", &list(strings(&pkg.provides)))?;
+        field(out, "Depends On", &list(strings(&pkg.depends)))?;
+        let optional = strings(&pkg.optional_depends);
+        match optional.split_first() {
+            Some((first, rest)) => {
+                field(out, "Optional Deps", first)?;
+                for x in rest {
+                    writeln!(out, "{:<18}{}", "", x)?;
+                }
+            }
+            None => field(out, "Optional Deps", "None")?,
+        }
+        let names = |x: Vec<&str>| list(x.into_iter().map(|x| x.to_owned()).collect());
+        field(out, "Required By", &names(reverse.required_by(&pkg.name)))?;
+        field(out, "Optional For", &names(reverse.optional_for(&pkg.name)))?;
+        field(out, "Conflicts With", &list(strings(&pkg.conflicts)))?;
+        field(out, "Replaces", &list(strings(&pkg.replaces)))?;
+        field(out, "Download Size", &human_size(pkg.size))?;
+        field(out, "Installed Size", &human_size(pkg.isize))?;
+        field(out, "Packager", &pkg.packager)?;
+        field(out, "Build Date", &date(&pkg.build_date))?;
+    }
+    Ok(())
+}
+
+/// Write the matching packages best first like `pacman -Ss`
+fn search_output(
+    out: &mut impl Write,
+    repos: &[RepoIndex],
+    search: &Search,
+    formatter: &SearchFormatter,
+) -> Result<()> {
+    formatter.write(out, &search.run(repos)?)?;
+    Ok(())
+}
+
+/// Write the dependencies of the package as a tree or in install order when flat
+fn deps(
+    out: &mut impl Write,
+    repos: &[RepoIndex],
+    name: &str,
+    flat: bool,
+    depth: Option<usize>,
+) -> Result<()> {
+    if flat {
+        for x in Resolver::new(repos).resolve(&[name])? {
+            writeln!(out, "{}/{} {}", x.repo, x.package.name, x.package.version)?;
+        }
+        return Ok(());
+    }
+
+    let satisfier = |dep: &Dependency| repos.iter().find_map(|x| x.satisfier(dep).ok());
+    let root = satisfier(&Dependency::from(name))
+        .ok_or_else(|| Error::PackageNotFound(name.to_owned()))?;
+    tree(out, &root.name, depth, |name| {
+        let pkg = match repos.iter().find_map(|x| x.get(name).ok()) {
+            Some(pkg) => pkg,
+            None => return Vec::new(),
+        };
+        pkg.depends
+            .iter()
+            .map(|dep| match satisfier(dep) {
+                Some(x) if x.name == dep.name => (dep.to_string(), Some(x.name.to_owned())),
+                Some(x) => (
+                    format!("{} provides {}", x.name, dep),
+                    Some(x.name.to_owned()),
+                ),
+                None => (format!("{} (not found)", dep), None),
+            })
+            .collect()
+    })?;
+    Ok(())
+}
+
+/// Write the packages requiring the package as a tree or every affected package when
+/// flat
+fn rdeps(
+    out: &mut impl Write,
+    repos: &[RepoIndex],
+    name: &str,
+    flat: bool,
+    depth: Option<usize>,
+) -> Result<()> {
+    if !repos.iter().any(|x| x.get(name).is_ok()) {
+        return Err(Error::PackageNotFound(name.to_owned()).into());
+    }
+    let reverse = ReverseIndex::new(repos);
+    if flat {
+        for x in reverse.transitive(name, DependencyKind::Depends) {
+            writeln!(out, "{}", x)?;
+        }
+        return Ok(());
+    }
+
+    tree(out, name, depth, |name| {
+        reverse
+            .required_by(name)
+            .into_iter()
+            .map(|x| (x.to_owned(), Some(x.to_owned())))
+            .collect()
+    })?;
+    Ok(())
+}
+
+/// Write a tree rooted at the given package. The children of a package are given
+/// as a label and the package to expand if any. Each package is only expanded once.
+fn tree<F>(out: &mut impl Write, root: &str, depth: Option<usize>, children: F) -> io::Result<()>
+where
+    F: Fn(&str) -> Vec<(String, Option<String>)>,
+{
+    fn branch<F>(
+        out: &mut impl Write,
+        name: &str,
+        prefix: &str,
+        depth: Option<usize>,
+        children: &F,
+        seen: &mut HashSet<String>,
+    ) -> io::Result<()>
+    where
+        F: Fn(&str) -> Vec<(String, Option<String>)>,
+    {
+        if depth == Some(0) {
+            return Ok(());
+        }
+        let nodes = children(name);
+        for (i, (label, child)) in nodes.iter().enumerate() {
+            let last = i + 1 == nodes.len();
+            writeln!(
+                out,
+                "{}{}{}",
+                prefix,
+                if last { "└─ " } else { "├─ " },
+                label
+            )?;
+            if let Some(child) = child.as_ref().filter(|x| seen.insert(x.to_string())) {
+                let prefix = format!("{}{}", prefix, if last { "   " } else { "│  " });
+                branch(out, child, &prefix, depth.map(|x| x - 1), children, seen)?;
+            }
+        }
+        Ok(())
+    }
+
+    writeln!(out, "{}", root)?;
+    let mut seen = HashSet::new();
+    seen.insert(root.to_owned());
+    branch(out, root, "", depth, &children, &mut seen)
+}
+
+/// Write the diff as a changelog or as JSON
+fn diff(out: &mut impl Write, diff: &DatabaseDiff, json: bool) -> Result<()> {
+    if json {
+        serde_json::to_writer_pretty(&mut *out, diff)?;
+        writeln!(out)?;
+    } else {
+        write!(out, "{}", diff)?;
+    }
+    Ok(())
+}
+
+/// Write the packages in the given format with dates in the given date format
+fn export(
+    out: &mut impl Write,
+    repos: &[RepoIndex],
+    repo: Option<&str>,
+    format: Format,
+    dates: DateFormat,
+) -> Result<()> {
+    let entries = filter(repos, repo)?
+        .into_iter()
+        .flat_map(|repo| {
+            repo.iter().map(move |package| Entry {
+                repo: repo.name(),
+                package,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, &Dated::new(&entries, dates))?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for x in &entries {
+                serde_json::to_writer(&mut *out, &Dated::new(x, dates))?;
+                writeln!(out)?;
+            }
+        }
+        Format::Csv => {
+            writeln!(
+                out,
+                "repo,name,base,version,arch,desc,url,size,isize,build_date,packager,\
+                 licenses,groups,provides,depends"
+            )?;
+            for x in &entries {
+                let pkg = x.package;
+                let build_date = match dates {
+                    DateFormat::Timestamp => pkg.build_date.to_owned(),
+                    DateFormat::Rfc3339 => date(&pkg.build_date),
+                };
+                let fields = [
+                    x.repo.to_owned(),
+                    pkg.name.to_owned(),
+                    pkg.base.to_owned(),
+                    pkg.version.to_string(),
+                    pkg.arch.to_owned(),
+                    pkg.desc.to_owned(),
+                    pkg.url.to_owned(),
+                    pkg.size.to_string(),
+                    pkg.isize.to_string(),
+                    build_date,
+                    pkg.packager.to_owned(),
+                    pkg.licenses.join(" "),
+                    pkg.groups.join(" "),
+                    strings(&pkg.provides).join(" "),
+                    strings(&pkg.depends).join(" "),
+                ];
+                let fields = fields.iter().map(|x| csv_field(x)).collect::<Vec<_>>();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Only keep the named repository if given
+fn filter<'a>(repos: &'a [RepoIndex], name: Option<&str>) -> Result<Vec<&'a RepoIndex>> {
+    match name {
+        None => Ok(repos.iter().collect()),
+        Some(name) => match repos.iter().find(|x| x.name() == name) {
+            Some(x) => Ok(vec![x]),
+            None => Err(Error::DatabaseNotFound(name.to_owned()).into()),
+        },
+    }
+}
+
+/// Format seconds since the epoch as RFC 3339 leaving anything else as is
+fn date(secs: &str) -> String {
+    match secs.parse() {
+        Ok(x) => format_rfc3339(x),
+        Err(_) => secs.to_owned(),
+    }
+}
+
+/// Format a size in bytes like pacman e.g. `1.50 MiB`
+fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, units[unit])
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repos() -> Vec<RepoIndex> {
+        let db = Database::default().with_path("tests/compression/none.db");
+        vec![RepoIndex::try_from(&db).unwrap().with_name("core")]
+    }
+
+    fn output<F: FnOnce(&mut Vec<u8>) -> Result<()>>(f: F) -> String {
+        let mut out = Vec::new();
+        f(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_list_search() {
+        let repos = repos();
+        let out = output(|x| list(x, &repos, Some("core")));
+        assert_eq!(out.lines().next(), Some("core acl 2.2.53-3"));
+        assert_eq!(out.lines().count(), 233);
+        assert!(list(&mut Vec::new(), &repos, Some("extra")).is_err());
+
+        let search = Search::new("^READ").with_regex(true).with_ignore_case(true);
+        let formatter = SearchFormatter::default();
+        let out = output(|x| search_output(x, &repos, &search, &formatter));
+        assert_eq!(out, "core/readline 8.1.0-2\n    GNU readline library\n");
+
+        let search = Search::new("^bash$").with_regex(true);
+        let installed = vec![repos[0].get("bash").unwrap().to_owned()];
+        let formatter = SearchFormatter::default().with_installed(&installed);
+        let out = output(|x| search_output(x, &repos, &search, &formatter));
+        assert_eq!(
+            out,
+            "core/bash 5.1.004-1 [installed]\n    The GNU Bourne Again shell\n"
+        );
+    }
+
+    #[test]
+    fn test_info() {
+        let repos = repos();
+        let out = output(|x| info(x, &repos, "sed"));
+        assert!(out.starts_with("Repository      : core\nName            : sed\n"));
+        assert!(out.contains("Depends On      : glibc  acl  attr\n"));
+        assert!(out.contains("Required By     : base  fakeroot\n"));
+        assert!(out.contains("Download Size   : 237.92 KiB\n"));
+        assert!(out.contains("Build Date      : 2020-01-15T13:07:59Z\n"));
+        assert!(info(&mut Vec::new(), &repos, "missing").is_err());
+    }
+
+    #[test]
+    fn test_deps() {
+        let repos = repos();
+        let out = output(|x| deps(x, &repos, "sed", false, None));
+        assert_eq!(
+            out,
+            "sed\n\
+             ├─ glibc\n\
+             │  ├─ linux-api-headers>=4.10\n\
+             │  ├─ tzdata\n\
+             │  └─ filesystem\n\
+             │     └─ iana-etc\n\
+             ├─ acl\n\
+             │  └─ attr\n\
+             │     └─ glibc\n\
+             └─ attr\n"
+        );
+        let out = output(|x| deps(x, &repos, "bzip2", false, Some(1)));
+        assert_eq!(out, "bzip2\n├─ glibc\n└─ bash provides sh\n");
+        let out = output(|x| deps(x, &repos, "make", false, Some(1)));
+        assert_eq!(out, "make\n├─ glibc\n└─ guile (not found)\n");
+
+        let out = output(|x| rdeps(x, &repos, "libmpc", true, None));
+        assert_eq!(out, "gcc\ngcc-ada\ngcc-d\ngcc-fortran\ngcc-go\ngcc-objc\n");
+        let out = output(|x| rdeps(x, &repos, "libmpc", false, Some(1)));
+        assert_eq!(out, "libmpc\n└─ gcc\n");
+    }
+
+    #[test]
+    fn test_export() {
+        let repos = repos();
+        let out = output(|x| export(x, &repos, None, Format::Ndjson, DateFormat::Timestamp));
+        let line: serde_json::Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+        assert_eq!(line["repo"], "core");
+        assert_eq!(line["name"], "acl");
+        assert_eq!(line["build_date"], 1588833464);
+        assert_eq!(out.lines().count(), 233);
+
+        let out = output(|x| export(x, &repos, None, Format::Csv, DateFormat::Rfc3339));
+        let mut lines = out.lines();
+        assert!(lines.next().unwrap().starts_with("repo,name,base,version"));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "core,acl,acl,2.2.53-3,x86_64,\"Access control list utilities, libraries and \
+                 headers\",https://savannah.nongnu.org/projects/acl,139908,331256,\
+                 2020-05-07T06:37:44Z,Christian Hesse <arch@eworm.de>,LGPL,,\
+                 xfsacl libacl.so=1-64,attr"
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512.00 B");
+        assert_eq!(human_size(1572864), "1.50 MiB");
+    }
+
+    #[test]
+    fn test_broken_pipe() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let repos = repos();
+        for format in [Format::Json, Format::Ndjson, Format::Csv] {
+            let e = export(&mut Closed, &repos, None, format, DateFormat::Timestamp).unwrap_err();
+            assert!(broken_pipe(e.as_ref()));
+        }
+        let e = list(&mut Closed, &repos, None).unwrap_err();
+        assert!(broken_pipe(e.as_ref()));
+        assert!(!broken_pipe(
+            list(&mut Vec::new(), &repos, Some("extra"))
+                .unwrap_err()
+                .as_ref()
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::error::*;
+use std::fmt;
+use std::io::{self, Cursor, Read};
+
+// Offset and value of the ustar magic within a tar header
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+/// `Compression` identifies the codec used for an archive e.g. a sync database
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+impl Compression {
+    /// Detect the compression from the magic bytes at the start of the data. A zero
+    /// block is an empty uncompressed archive e.g. as left by `repo-remove`.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if data.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if is_tar(data) {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+
+    /// Check if support for this codec was compiled in
+    pub fn is_supported(&self) -> bool {
+        match *self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Xz => cfg!(feature = "xz"),
+            Compression::Bzip2 => cfg!(feature = "bzip2"),
+        }
+    }
+
+    /// File extension pacman uses for archives with this codec e.g. `gz` for
+    /// `core.db.tar.gz`, empty for an uncompressed archive
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Compression::None => "",
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bz2",
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+            Compression::Xz => write!(f, "xz"),
+            Compression::Bzip2 => write!(f, "bzip2"),
+        }
+    }
+}
+
+/// Check for the ustar magic or the zero block ending an empty archive
+fn is_tar(data: &[u8]) -> bool {
+    match data.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) {
+        Some(magic) => magic == TAR_MAGIC || data.iter().all(|&x| x == 0),
+        None => false,
+    }
+}
+
+/// Wrap the given reader in a decoder chosen by sniffing its magic bytes. Failing to
+/// read the magic bytes is reported with the given error e.g. `Error::DatabaseLoad`.
+pub(crate) fn decoder<'a>(
+    mut reader: impl Read + Send + 'a,
+    load: impl FnOnce(io::Error) -> Error,
+) -> Result<Box<dyn Read + Send + 'a>> {
+    // Buffer enough of the stream to see the tar magic then stitch it back on
+    let mut magic = Vec::with_capacity(TAR_MAGIC_OFFSET + TAR_MAGIC.len());
+    (&mut reader)
+        .take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+        .read_to_end(&mut magic)
+        .map_err(load)?;
+    let compression = Compression::detect(&magic).ok_or(Error::CompressionUnknown)?;
+    let reader = Cursor::new(magic).chain(reader);
+
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::read::GzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(
+            zstd::stream::read::Decoder::new(reader)
+                .map_err(|_| Error::CompressionUnsupported(compression))?,
+        )),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(xz2::read::XzDecoder::new(reader))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(bzip2::read::BzDecoder::new(reader))),
+        #[allow(unreachable_patterns)]
+        _ => Err(Error::CompressionUnsupported(compression)),
+    }
+}
+
+/// Compress the data in memory with the given codec e.g. when writing a database.
+/// Io errors are mapped with the given function e.g. to `Error::DatabaseWrite`.
+pub(crate) fn compress(
+    data: &[u8],
+    compression: Compression,
+    write: impl FnOnce(io::Error) -> Error,
+) -> Result<Vec<u8>> {
+    let compressed: std::io::Result<Vec<u8>> = match compression {
+        Compression::None => Ok(data.to_vec()),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+            let level = flate2::Compression::default();
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::stream::encode_all(data, 0),
+        #[cfg(feature = "xz")]
+        Compression::Xz => read_all(xz2::read::XzEncoder::new(data, 6)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => {
+            let level = bzip2::Compression::default();
+            read_all(bzip2::read::BzEncoder::new(data, level))
+        }
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::CompressionUnsupported(compression)),
+    };
+    compressed.map_err(write)
+}
+
+#[cfg(any(feature = "xz", feature = "bzip2"))]
+fn read_all(mut reader: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(e: io::Error) -> Error {
+        Error::DatabaseLoad("test.db".into(), e)
+    }
+
+    fn write(e: io::Error) -> Error {
+        Error::DatabaseWrite("test.db".into(), e)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Compression::detect(&[0x1f, 0x8b, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::detect(b"\xfd7zXZ\x00\x00"),
+            Some(Compression::Xz)
+        );
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::detect(b"foo"), None);
+
+        let mut header = vec![0u8; 512];
+        header[257..262].copy_from_slice(b"ustar");
+        assert_eq!(Compression::detect(&header), Some(Compression::None));
+    }
+
+    #[test]
+    #[cfg(all(feature = "gzip", feature = "zstd", feature = "xz", feature = "bzip2"))]
+    fn test_decoder() {
+        use std::fs::File;
+
+        let mut expected = Vec::new();
+        File::open("tests/compression/none.db")
+            .unwrap()
+            .read_to_end(&mut expected)
+            .unwrap();
+
+        for path in &[
+            "tests/sync/core.db",
+            "tests/compression/none.db",
+            "tests/compression/zstd.db",
+            "tests/compression/xz.db",
+            "tests/compression/bzip2.db",
+        ] {
+            let mut data = Vec::new();
+            decoder(File::open(path).unwrap(), load)
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_compress() {
+        let data = std::fs::read("tests/compression/none.db").unwrap();
+        for &compression in &[
+            Compression::None,
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Xz,
+            Compression::Bzip2,
+        ] {
+            if !compression.is_supported() {
+                assert!(matches!(
+                    compress(&data, compression, write),
+                    Err(Error::CompressionUnsupported(_))
+                ));
+                continue;
+            }
+            let compressed = compress(&data, compression, write).unwrap();
+            assert_eq!(Compression::detect(&compressed), Some(compression));
+
+            let mut decompressed = Vec::new();
+            decoder(&compressed[..], load)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, data, "{}", compression);
+        }
+    }
+
+    #[test]
+    fn test_decoder_unknown() {
+        assert!(matches!(
+            decoder(&b"not an archive"[..], load),
+            Err(Error::CompressionUnknown)
+        ));
+    }
+
+    #[test]
+    fn test_decoder_empty_archive() {
+        let data = vec![0u8; 1024];
+        assert_eq!(Compression::detect(&data), Some(Compression::None));
+        let entries = crate::tar::TarReader::new(decoder(&data[..], load).unwrap());
+        assert_eq!(entries.count(), 0);
+    }
+
+    #[test]
+    fn test_decoder_read_error() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+        }
+        assert!(matches!(
+            decoder(Failing, load),
+            Err(Error::DatabaseLoad(_, e)) if e.kind() == io::ErrorKind::PermissionDenied
+        ));
+    }
+}
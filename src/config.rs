@@ -0,0 +1,360 @@
+use crate::prelude::*;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG: &str = "/etc/pacman.conf";
+
+// Maximum nesting of `Include` directives, the same limit pacman applies
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// `Config` holds the settings and repositories parsed from a `pacman.conf`
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    pub root_dir: PathBuf,         // Root directory for installation e.g. `/`
+    pub db_path: Option<PathBuf>,  // Database directory when not derived from root_dir
+    pub cache_dir: Vec<PathBuf>,   // Package cache directories e.g. `/var/cache/pacman/pkg/`
+    pub gpg_dir: Option<PathBuf>,  // Keyring directory e.g. `/etc/pacman.d/gnupg/`
+    pub architecture: Vec<String>, // Allowed architectures e.g. `auto` or `x86_64`
+    pub sig_level: Vec<String>,    // Default signature policy e.g. `Required DatabaseOptional`
+    pub hold_pkg: Vec<String>,
+    pub ignore_pkg: Vec<String>,
+    pub ignore_group: Vec<String>,
+    pub repos: Vec<Repo>, // Repositories in priority order
+}
+
+/// `Repo` is a repository section of a `pacman.conf` e.g. `[core]`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Repo {
+    pub name: String,
+    pub servers: Vec<String>, // Server urls as given e.g. `https://host/$repo/os/$arch`
+    pub sig_level: Vec<String>, // Signature policy overriding the `[options]` default
+}
+
+impl Config {
+    /// Load and parse the given config file following any `Include` directives
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut config = Self::default();
+        let mut section = None;
+        config.include(path.as_ref(), &mut section, 0)?;
+        Ok(config)
+    }
+
+    /// Set the root directory e.g. when inspecting a mounted system image
+    pub fn with_root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.root_dir = dir.into();
+        self
+    }
+
+    /// Set the database directory overriding the `DBPath` option
+    pub fn with_db_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.db_path = Some(dir.into());
+        self
+    }
+
+    /// Database directory taking the root directory into account as pacman does
+    pub fn db_dir(&self) -> PathBuf {
+        match self.db_path {
+            Some(ref dir) => dir.to_owned(),
+            None => self.root_dir.join(DEFAULT_DB_DIR.trim_start_matches('/')),
+        }
+    }
+
+    /// Allowed architectures with `auto` resolved to that of the running machine as
+    /// pacman does, `auto` when none are configured
+    pub fn architectures(&self) -> Vec<String> {
+        let auto = || match std::env::consts::ARCH {
+            "x86" => "i686".to_owned(),
+            x => x.to_owned(),
+        };
+        if self.architecture.is_empty() {
+            return vec![auto()];
+        }
+        self.architecture
+            .iter()
+            .map(|x| if x == "auto" { auto() } else { x.to_owned() })
+            .collect()
+    }
+
+    /// Construct the set of sync databases for the configured repositories. Each
+    /// database keeps the server urls of its repository for the first architecture
+    /// and its signature policy, the `[options]` default followed by the override.
+    pub fn databases(&self) -> DatabaseSet {
+        let dir = self.db_dir();
+        let arch = self.architectures().remove(0);
+        self.repos
+            .iter()
+            .map(|x| {
+                let sig_level = self.sig_level.iter().chain(&x.sig_level).cloned();
+                Database::default()
+                    .with_dir(&dir)
+                    .with_name(&x.name)
+                    .with_servers(x.server_urls(&arch))
+                    .with_sig_level(sig_level.collect())
+            })
+            .collect()
+    }
+
+    /// Get a repository by name
+    pub fn repo(&self, name: &str) -> Option<&Repo> {
+        self.repos.iter().find(|x| x.name == name)
+    }
+
+    /// Read the given file and parse it into this config
+    fn include(&mut self, path: &Path, section: &mut Option<String>, depth: usize) -> Result<()> {
+        let data = fs::read_to_string(path).map_err(|e| Error::ConfigLoad(path.to_owned(), e))?;
+        self.parse(&data, section, depth)
+    }
+
+    /// Parse config text into this config. The current section carries across
+    /// included files just like pacman. The depth counts the enclosing includes.
+    fn parse(&mut self, data: &str, section: &mut Option<String>, depth: usize) -> Result<()> {
+        for line in data.lines().map(|x| x.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Section headers e.g. `[options]` or `[core]`
+            if line.starts_with('[') {
+                let name = line
+                    .strip_prefix('[')
+                    .and_then(|x| x.strip_suffix(']'))
+                    .filter(|x| !x.is_empty())
+                    .ok_or_else(|| Error::ConfigParse(line.to_owned()))?;
+                if name != "options" && self.repo(name).is_none() {
+                    self.repos.push(Repo {
+                        name: name.to_owned(),
+                        ..Default::default()
+                    });
+                }
+                *section = Some(name.to_owned());
+                continue;
+            }
+
+            // Directives outside a section are invalid
+            let name = match section {
+                Some(ref x) => x.to_owned(),
+                None => return Err(Error::ConfigParse(line.to_owned())),
+            };
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (line, ""),
+            };
+            let values = || value.split_whitespace().map(|x| x.to_owned());
+
+            if key == "Include" {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(Error::ConfigParse(line.to_owned()));
+                }
+                let paths = glob::glob(value).map_err(|_| Error::ConfigParse(line.to_owned()))?;
+                let mut paths = paths.filter_map(|x| x.ok()).collect::<Vec<_>>();
+                paths.sort();
+                for path in paths {
+                    self.include(&path, section, depth + 1)?;
+                }
+                continue;
+            }
+
+            if name == "options" {
+                match key {
+                    "RootDir" => self.root_dir = PathBuf::from(value),
+                    "DBPath" => self.db_path = Some(PathBuf::from(value)),
+                    "CacheDir" => self.cache_dir.extend(values().map(PathBuf::from)),
+                    "GPGDir" => self.gpg_dir = Some(PathBuf::from(value)),
+                    "Architecture" => self.architecture.extend(values()),
+                    "SigLevel" => self.sig_level.extend(values()),
+                    "HoldPkg" => self.hold_pkg.extend(values()),
+                    "IgnorePkg" => self.ignore_pkg.extend(values()),
+                    "IgnoreGroup" => self.ignore_group.extend(values()),
+                    _ => {}
+                }
+            } else if let Some(repo) = self.repos.iter_mut().find(|x| x.name == name) {
+                match key {
+                    "Server" => repo.servers.push(value.to_owned()),
+                    "SigLevel" => repo.sig_level.extend(values()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for Config {
+    type Error = Error;
+
+    /// Parse config text, `Include` paths are still read from disk
+    fn try_from(data: &str) -> Result<Self> {
+        let mut config = Self::default();
+        config.parse(data, &mut None, 0)?;
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            root_dir: PathBuf::from("/"),
+            db_path: None,
+            cache_dir: Default::default(),
+            gpg_dir: None,
+            architecture: Default::default(),
+            sig_level: Default::default(),
+            hold_pkg: Default::default(),
+            ignore_pkg: Default::default(),
+            ignore_group: Default::default(),
+            repos: Default::default(),
+        }
+    }
+}
+
+impl Repo {
+    /// Server urls with `$repo` and `$arch` substituted
+    pub fn server_urls(&self, arch: &str) -> Vec<String> {
+        self.servers
+            .iter()
+            .map(|x| x.replace("$repo", &self.name).replace("$arch", arch))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let config = Config::load("tests/pacman.conf").unwrap();
+        assert_eq!(config.root_dir, PathBuf::from("/"));
+        assert_eq!(config.db_dir(), PathBuf::from("tests/"));
+        assert_eq!(
+            config.cache_dir,
+            vec![PathBuf::from("/var/cache/pacman/pkg/")]
+        );
+        assert_eq!(config.architecture, vec!["auto"]);
+        assert_eq!(config.sig_level, vec!["Required", "DatabaseOptional"]);
+        assert_eq!(config.hold_pkg, vec!["pacman", "glibc"]);
+        assert_eq!(config.ignore_pkg, vec!["linux", "linux-headers", "nvidia"]);
+        assert_eq!(config.ignore_group, vec!["gnome"]);
+
+        let names = config
+            .repos
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["core", "extra", "custom", "testing"]);
+
+        let core = config.repo("core").unwrap();
+        assert_eq!(
+            core.servers,
+            vec![
+                "https://mirror.example.com/archlinux/$repo/os/$arch",
+                "https://mirror2.example.com/archlinux/$repo/os/$arch"
+            ]
+        );
+        assert_eq!(
+            core.server_urls("x86_64")[0],
+            "https://mirror.example.com/archlinux/core/os/x86_64"
+        );
+        assert!(core.sig_level.is_empty());
+        assert_eq!(
+            config.repo("extra").unwrap().sig_level,
+            vec!["Optional", "TrustAll"]
+        );
+        assert_eq!(config.repo("extra").unwrap().servers.len(), 2);
+        assert_eq!(config.repo("custom").unwrap().sig_level, vec!["Never"]);
+        assert_eq!(
+            config.repo("custom").unwrap().servers,
+            vec!["file:///srv/repo/$repo/$arch"]
+        );
+
+        // The last section of an included file carries over to the including file
+        assert_eq!(
+            config.repo("testing").unwrap().servers,
+            vec![
+                "https://testing.example.com/$repo/$arch",
+                "https://fallback.example.com/$repo/$arch"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_architectures() {
+        let config = Config::try_from("[options]\nArchitecture = auto armv7h\n").unwrap();
+        let arch = config.architectures();
+        assert_eq!(arch.len(), 2);
+        assert_ne!(arch[0], "auto");
+        assert_eq!(arch[1], "armv7h");
+        assert_eq!(Config::default().architectures().len(), 1);
+    }
+
+    #[test]
+    fn test_sig_level_lines() {
+        let data = "[options]\nSigLevel = Required\nSigLevel = DatabaseOptional\n\
+                    [core]\nSigLevel = Optional\nSigLevel = TrustAll\n";
+        let config = Config::try_from(data).unwrap();
+        assert_eq!(config.sig_level, vec!["Required", "DatabaseOptional"]);
+        assert_eq!(
+            config.repo("core").unwrap().sig_level,
+            vec!["Optional", "TrustAll"]
+        );
+    }
+
+    #[test]
+    fn test_root_dir() {
+        let config = Config::try_from("[options]\nRootDir = /mnt\n").unwrap();
+        assert_eq!(config.db_dir(), PathBuf::from("/mnt/var/lib/pacman"));
+
+        let config = config.with_db_path("/tmp/db");
+        assert_eq!(config.db_dir(), PathBuf::from("/tmp/db"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Config::try_from("IgnorePkg = foo\n"),
+            Err(Error::ConfigParse(x)) if x == "IgnorePkg = foo"
+        ));
+        assert!(matches!(
+            Config::try_from("[]\n"),
+            Err(Error::ConfigParse(_))
+        ));
+        assert!(Config::try_from("[core]\nInclude = tests/missing\n").is_ok());
+        assert!(matches!(
+            Config::load("tests/missing.conf"),
+            Err(Error::ConfigLoad(_, ref e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+
+        // A file including itself stops at the include depth limit
+        assert!(matches!(
+            Config::load("tests/pacman.d/recursive"),
+            Err(Error::ConfigParse(x)) if x == "Include = tests/pacman.d/recursive"
+        ));
+    }
+
+    #[test]
+    fn test_databases() {
+        let dbs = Config::load("tests/pacman.conf").unwrap().databases();
+        assert_eq!(dbs.len(), 4);
+        assert_eq!(
+            dbs.get("core").unwrap().path(),
+            Path::new("tests/sync/core.db")
+        );
+        let names = dbs.iter().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["core", "extra", "custom", "testing"]);
+
+        let arch = Config::default().architectures().remove(0);
+        let core = dbs.get("core").unwrap();
+        assert_eq!(
+            core.servers()[0],
+            format!("https://mirror.example.com/archlinux/core/os/{}", arch)
+        );
+        assert_eq!(core.sig_level(), ["Required", "DatabaseOptional"]);
+        assert_eq!(
+            dbs.get("extra").unwrap().sig_level(),
+            ["Required", "DatabaseOptional", "Optional", "TrustAll"]
+        );
+    }
+}
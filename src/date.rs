@@ -0,0 +1,750 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize, Serializer};
+use std::fmt;
+
+/// Name of the newtype struct wrapping each date so `Dated` can tell it apart. Other
+/// serializers write the seconds within as is.
+const DATE: &str = "$package_iter::Date";
+
+/// `DateFormat` selects how `Dated` serializes dates e.g. `Package::build_date`.
+/// Either format is accepted when deserializing.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum DateFormat {
+    /// Seconds since the epoch as a number e.g. `1588833495`
+    #[default]
+    Timestamp,
+
+    /// RFC 3339 text in UTC e.g. `2020-05-07T06:38:15Z`
+    Rfc3339,
+}
+
+/// `Dated` serializes a value with its dates in the given format, dates are
+/// timestamps otherwise e.g.
+/// `serde_json::to_string(&Dated::new(&pkg, DateFormat::Rfc3339))`
+#[derive(Debug, Clone, Copy)]
+pub struct Dated<'a, T: ?Sized> {
+    value: &'a T,       // Value to serialize
+    format: DateFormat, // Format of the dates within
+}
+impl<'a, T: ?Sized> Dated<'a, T> {
+    /// Wrap the value to serialize its dates in the given format
+    pub fn new(value: &'a T, format: DateFormat) -> Self {
+        Self { value, format }
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for Dated<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.format {
+            DateFormat::Timestamp => self.value.serialize(serializer),
+            DateFormat::Rfc3339 => self.value.serialize(Rfc3339(serializer)),
+        }
+    }
+}
+
+/// Format seconds since the epoch as RFC 3339 in UTC e.g. `2020-05-07T06:38:15Z`
+pub fn format_rfc3339(secs: i64) -> String {
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parse RFC 3339 text e.g. `2020-05-07T08:38:15+02:00` into seconds since the epoch.
+/// Fractional seconds are dropped.
+pub fn parse_rfc3339(data: &str) -> Option<i64> {
+    let num = |x: &str| -> Option<i64> {
+        Some(x)
+            .filter(|x| !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit()))?
+            .parse()
+            .ok()
+    };
+    let (date, time) = data.split_at(data.find(['T', 't', ' '])?);
+    let mut date = date.splitn(3, '-');
+    let (year, month, day) = (num(date.next()?)?, num(date.next()?)?, num(date.next()?)?);
+    let time = &time[1..];
+
+    // Split off the offset e.g. `Z` or `+02:00`
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => return None,
+    };
+    let offset = match offset {
+        "Z" | "z" => 0,
+        x => {
+            let (hours, minutes) = x[1..].split_once(':')?;
+            let secs = num(hours)? * 3600 + num(minutes)? * 60;
+            if x.starts_with('-') {
+                -secs
+            } else {
+                secs
+            }
+        }
+    };
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':');
+    let (hour, minute, second) = (num(time.next()?)?, num(time.next()?)?, num(time.next()?)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    // Days since the epoch from the civil date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Serialize seconds since the epoch marked as a date for `Dated`
+fn serialize_secs<S: Serializer>(secs: i64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_newtype_struct(DATE, &secs)
+}
+
+/// `Rfc3339` passes everything through to the serializer it wraps except dates
+/// which are written as RFC 3339 text, along with the compound serializers
+struct Rfc3339<S>(S);
+
+/// Values nested in a compound keep their dates as RFC 3339 text
+fn rfc3339<T: ?Sized>(value: &T) -> Dated<'_, T> {
+    Dated::new(value, DateFormat::Rfc3339)
+}
+
+impl<S: Serializer> Serializer for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Rfc3339<S::SerializeSeq>;
+    type SerializeTuple = Rfc3339<S::SerializeTuple>;
+    type SerializeTupleStruct = Rfc3339<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Rfc3339<S::SerializeTupleVariant>;
+    type SerializeMap = Rfc3339<S::SerializeMap>;
+    type SerializeStruct = Rfc3339<S::SerializeStruct>;
+    type SerializeStructVariant = Rfc3339<S::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.0.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.0.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.0.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.0.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.0.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        self.0.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.0.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.0.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.0.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.0.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        self.0.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.0.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.0.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.0.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.0.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.0.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.0.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.0.serialize_some(&rfc3339(value))
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.0.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.0.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.0.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        match name {
+            DATE => value.serialize(Rfc3339Date(self.0)),
+            _ => self.0.serialize_newtype_struct(name, &rfc3339(value)),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.0
+            .serialize_newtype_variant(name, index, variant, &rfc3339(value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        self.0.serialize_seq(len).map(Rfc3339)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        self.0.serialize_tuple(len).map(Rfc3339)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        self.0.serialize_tuple_struct(name, len).map(Rfc3339)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        self.0
+            .serialize_tuple_variant(name, index, variant, len)
+            .map(Rfc3339)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        self.0.serialize_map(len).map(Rfc3339)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        self.0.serialize_struct(name, len).map(Rfc3339)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        self.0
+            .serialize_struct_variant(name, index, variant, len)
+            .map(Rfc3339)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+impl<S: ser::SerializeSeq> ser::SerializeSeq for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_element(&rfc3339(value))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTuple> ser::SerializeTuple for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_element(&rfc3339(value))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_field(&rfc3339(value))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_field(&rfc3339(value))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeMap> ser::SerializeMap for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        self.0.serialize_key(&rfc3339(key))
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.0.serialize_value(&rfc3339(value))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeStruct> ser::SerializeStruct for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        self.0.serialize_field(key, &rfc3339(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S: ser::SerializeStructVariant> ser::SerializeStructVariant for Rfc3339<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        self.0.serialize_field(key, &rfc3339(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+/// `Rfc3339Date` writes the seconds within a date as RFC 3339 text. Dates only ever
+/// hold seconds so anything else is an error.
+struct Rfc3339Date<S>(S);
+
+/// Reject a value other than the seconds of a date
+macro_rules! not_seconds {
+    ($($method:ident($($ty:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<$ok, S::Error> {
+                Err(ser::Error::custom("date is not seconds since the epoch"))
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for Rfc3339Date<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Impossible<S::Ok, S::Error>;
+    type SerializeTuple = Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = Impossible<S::Ok, S::Error>;
+    type SerializeMap = Impossible<S::Ok, S::Error>;
+    type SerializeStruct = Impossible<S::Ok, S::Error>;
+    type SerializeStructVariant = Impossible<S::Ok, S::Error>;
+
+    fn serialize_i64(self, secs: i64) -> Result<S::Ok, S::Error> {
+        self.0.serialize_str(&format_rfc3339(secs))
+    }
+
+    not_seconds! {
+        serialize_bool(bool) -> S::Ok;
+        serialize_i8(i8) -> S::Ok;
+        serialize_i16(i16) -> S::Ok;
+        serialize_i32(i32) -> S::Ok;
+        serialize_u8(u8) -> S::Ok;
+        serialize_u16(u16) -> S::Ok;
+        serialize_u32(u32) -> S::Ok;
+        serialize_u64(u64) -> S::Ok;
+        serialize_f32(f32) -> S::Ok;
+        serialize_f64(f64) -> S::Ok;
+        serialize_char(char) -> S::Ok;
+        serialize_str(&str) -> S::Ok;
+        serialize_bytes(&[u8]) -> S::Ok;
+        serialize_none() -> S::Ok;
+        serialize_unit() -> S::Ok;
+        serialize_unit_struct(&'static str) -> S::Ok;
+        serialize_unit_variant(&'static str, u32, &'static str) -> S::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("date is not seconds since the epoch"))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("date is not seconds since the epoch"))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("date is not seconds since the epoch"))
+    }
+}
+
+/// `DateVisitor` accepts a timestamp, RFC 3339 text or nothing returning the seconds
+/// since the epoch as text as kept by `Package` e.g. `1588833495`. Other text is
+/// returned as is.
+struct DateVisitor;
+impl<'de> Visitor<'de> for DateVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seconds since the epoch or an RFC 3339 date")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+        match parse_rfc3339(value) {
+            Some(secs) => Ok(secs.to_string()),
+            None => Ok(value.to_owned()),
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<String, E> {
+        Ok(String::new())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<String, E> {
+        Ok(String::new())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+/// Serde helpers for a date kept as text seconds since the epoch e.g. `%BUILDDATE%`.
+/// Empty text is serialized as nothing and text that isn't a number is kept as is in
+/// both directions.
+pub(crate) mod text {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        match value.parse::<i64>() {
+            Ok(secs) => serialize_secs(secs, serializer),
+            Err(_) if value.is_empty() => serializer.serialize_none(),
+            Err(_) => serializer.serialize_str(value),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<String, D::Error> {
+        deserializer.deserialize_any(DateVisitor)
+    }
+}
+
+/// Serde helpers for a date as seconds since the epoch where zero is unknown e.g.
+/// `%INSTALLDATE%`
+pub(crate) mod number {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        match *value {
+            0 => serializer.serialize_none(),
+            secs => serialize_secs(secs as i64, serializer),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let value = deserializer.deserialize_any(DateVisitor)?;
+        match value.as_str() {
+            "" => Ok(0),
+            x => x.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Serde helpers for an optional date as seconds since the epoch
+pub(crate) mod seconds {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        value: &Option<u32>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match *value {
+            Some(secs) => serialize_secs(secs as i64, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        let value = deserializer.deserialize_any(DateVisitor)?;
+        match value.as_str() {
+            "" => Ok(None),
+            x => x.parse().map(Some).map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::prelude::*;
+
+    fn package() -> Package {
+        Package {
+            build_date: "1588833495".to_owned(),
+            optional_depends: vec![OptionalDependency::from("perl: for the scripts")],
+            ..fixtures::package("acl", "2.2.53-3", &["attr>=2.4"])
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1588833495), "2020-05-07T06:38:15Z");
+        assert_eq!(format_rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(parse_rfc3339("2020-05-07T06:38:15Z"), Some(1588833495));
+        assert_eq!(
+            parse_rfc3339("2020-05-07T08:38:15.5+02:00"),
+            Some(1588833495)
+        );
+        assert_eq!(parse_rfc3339("2000-02-29 00:00:00z"), Some(951782400));
+        assert_eq!(parse_rfc3339("2020-05-07"), None);
+        assert_eq!(parse_rfc3339("2020-13-07T06:38:15Z"), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let pkg = package();
+        let data = serde_json::to_value(&pkg).unwrap();
+        assert_eq!(data["name"], "acl");
+        assert_eq!(data["version"], "2.2.53-3");
+        assert_eq!(data["build_date"], 1588833495);
+        assert_eq!(data["depends"][0], "attr>=2.4");
+        assert_eq!(data["optional_depends"][0], "perl: for the scripts");
+        assert_eq!(data["packager"], "");
+        assert_eq!(serde_json::from_value::<Package>(data).unwrap(), pkg);
+
+        let data = serde_json::to_value(Dated::new(&pkg, DateFormat::Rfc3339)).unwrap();
+        assert_eq!(data["build_date"], "2020-05-07T06:38:15Z");
+        assert_eq!(serde_json::from_value::<Package>(data).unwrap(), pkg);
+
+        // Nested and flattened dates too
+        #[derive(serde::Serialize)]
+        struct Entry<'a> {
+            repo: &'a str,
+            #[serde(flatten)]
+            package: &'a Package,
+        }
+        let entries = vec![Entry {
+            repo: "core",
+            package: &pkg,
+        }];
+        let data = serde_json::to_value(Dated::new(&entries, DateFormat::Rfc3339)).unwrap();
+        assert_eq!(data[0]["build_date"], "2020-05-07T06:38:15Z");
+        assert_eq!(data[0]["size"], 0);
+        let data = serde_json::to_value(Dated::new(&entries, DateFormat::Timestamp)).unwrap();
+        assert_eq!(data[0]["build_date"], 1588833495);
+    }
+
+    #[test]
+    fn test_msgpack() {
+        let pkg = InstalledPackage {
+            package: package(),
+            install_date: 1589000000,
+            reason: InstallReason::Dependency,
+            validation: vec![Validation::Signature],
+            ..Default::default()
+        };
+        let data = rmp_serde::to_vec_named(&pkg).unwrap();
+        assert_eq!(
+            rmp_serde::from_slice::<InstalledPackage>(&data).unwrap(),
+            pkg
+        );
+        let data = serde_json::to_value(&pkg).unwrap();
+        assert_eq!(data["reason"], "dependency");
+        assert_eq!(data["validation"][0], "signature");
+    }
+
+    #[test]
+    fn test_signature() {
+        let data = std::fs::read("tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst.sig").unwrap();
+        let mut sig = Signature::from_bytes(&data).unwrap();
+        let data = serde_json::to_value(Dated::new(&sig, DateFormat::Rfc3339)).unwrap();
+        assert_eq!(data["created"], "2021-01-07T00:00:00Z");
+        assert_eq!(data["public_key_algorithm"], "eddsa");
+        assert!(data.get("hashed").is_none());
+        assert!(data.get("values").is_none());
+
+        // The raw signature data is left out
+        let decoded = serde_json::from_value::<Signature>(data).unwrap();
+        assert_eq!(decoded.created, sig.created);
+        assert_eq!(decoded.issuer_fingerprint, sig.issuer_fingerprint);
+        assert!(decoded.values.is_empty());
+
+        sig.created = None;
+        let data = serde_json::to_value(&sig).unwrap();
+        assert!(data["created"].is_null());
+        assert_eq!(
+            serde_json::from_value::<Signature>(data).unwrap().created,
+            None
+        );
+    }
+
+    #[test]
+    fn test_unparsed_date() {
+        // Text dates that aren't a number round trip as is in every format
+        let pkg = Package {
+            build_date: "garbage".to_owned(),
+            ..package()
+        };
+        for format in &[DateFormat::Timestamp, DateFormat::Rfc3339] {
+            let data = serde_json::to_string(&Dated::new(&pkg, *format)).unwrap();
+            assert_eq!(serde_json::from_str::<Package>(&data).unwrap(), pkg);
+        }
+        let data = rmp_serde::to_vec_named(&pkg).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Package>(&data).unwrap(), pkg);
+
+        // Numeric dates still reject them
+        let mut data = serde_json::to_value(InstalledPackage::default()).unwrap();
+        data["install_date"] = "yesterday".into();
+        assert!(serde_json::from_value::<InstalledPackage>(data).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_roundtrip_db() {
+        let db = Database::default().with_path("tests/sync/core.db");
+        let pkgs = db.packages().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        let data = rmp_serde::to_vec_named(&pkgs).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Vec<Package>>(&data).unwrap(), pkgs);
+        let data = serde_json::to_string(&Dated::new(&pkgs, DateFormat::Rfc3339)).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Package>>(&data).unwrap(), pkgs);
+    }
+}
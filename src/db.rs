@@ -1,21 +1,81 @@
+use crate::compression::decoder;
+use crate::parser::parse_files;
 use crate::prelude::*;
-use ::tar::{Archive, Entries, Entry};
-use flate2::read::GzDecoder;
+use crate::tar::{TarEntry, TarReader};
+use ::tar::EntryType;
+use std::convert::TryFrom;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::Read;
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
 
-pub const DEFAULT_DB_DIR: &'static str = "/var/lib/pacman";
+pub const DEFAULT_DB_DIR: &str = "/var/lib/pacman";
 
 /// `Database` encapsulates the functionality for packages
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Database {
-    name: String,  // Name of the database to load e.g. `core`
-    dir: PathBuf,  // Directory to search for databases e.g. `/var/lib/pacman`
-    path: PathBuf, // Full path to the database e.g. `/var/lib/pacman/sync/core.db`
+    name: String,           // Name of the database to load e.g. `core`
+    dir: PathBuf,           // Directory to search for databases e.g. `/var/lib/pacman`
+    path: PathBuf,          // Full path to the database e.g. `/var/lib/pacman/sync/core.db`
+    explicit: bool,         // Path was set with `with_path` rather than derived from the name
+    files: bool,            // Load the `.files` database including package file lists
+    options: ParseOptions,  // Validation applied to each package desc
+    servers: Vec<String>,   // Server urls of the repository e.g. from `pacman.conf`
+    sig_level: Vec<String>, // Signature policy values e.g. `Required DatabaseOptional`
 }
 impl Database {
     /// Set the directory to search for databases
     pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
         self.dir = dir.into();
+        self.sync_path();
+        self
+    }
+
+    /// Set the name of the database to load e.g. `core`
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_lowercase();
+        self.sync_path();
+        self
+    }
+
+    /// Load the `.files` variant of the database e.g. `core.files` which includes the
+    /// file list of each package
+    pub fn with_files(mut self, files: bool) -> Self {
+        self.files = files;
+        self.sync_path();
+        self
+    }
+
+    /// Set the validation applied to each package e.g. `ParseOptions::strict()`
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the server urls of the repository e.g. `https://host/core/os/x86_64`
+    pub fn with_servers(mut self, servers: Vec<String>) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    /// Set the signature policy values as given in `pacman.conf`, later values take
+    /// priority e.g. see `SigLevel::database`
+    pub fn with_sig_level(mut self, sig_level: Vec<String>) -> Self {
+        self.sig_level = sig_level;
+        self
+    }
+
+    /// Set the full path to the database file, bypassing the directory search.
+    /// The database name is taken from the file name e.g. `core` for `core.db` or
+    /// `core.db.tar.gz`. Later calls to `with_dir`, `with_name` or `with_files` keep
+    /// this path.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = path.into();
+        self.explicit = true;
+        if let Some(name) = db_name(&self.path) {
+            self.name = name;
+        }
         self
     }
 
@@ -25,28 +85,76 @@ impl Database {
     ///
     /// Default: loads from /var/lib/pacman
     pub fn load(name: &str) -> Result<Self> {
-        let mut db = Self {
-            name: name.to_owned().to_lowercase(),
-            ..Default::default()
-        };
-        db.path = db.dir.join("sync").join(format!("{}.db", db.name));
+        let db = Self::default().with_name(name);
 
         // Validate the database exists
-        if db.path.is_file() {
+        if !db.path.is_file() {
             return Err(Error::DatabaseNotFound(db.name));
         }
 
         Ok(db)
     }
 
+    /// Name of the database e.g. `core`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Full path to the database file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Server urls of the repository
+    pub fn servers(&self) -> &[String] {
+        &self.servers
+    }
+
+    /// Signature policy values of the repository
+    pub fn sig_level(&self) -> &[String] {
+        &self.sig_level
+    }
+
     /// Construct an iterator over the packages in this database
     pub fn packages(&self) -> Result<Packages> {
+        if !self.path.is_file() {
+            return Err(Error::DatabaseNotFound(self.name.to_owned()));
+        }
+        let load = |e| Error::DatabaseLoad(self.path.to_owned(), e);
+        let f = File::open(&self.path).map_err(load)?;
+
         Ok(Packages {
-            path: PathBuf::from(&self.path),
-            archive: None,
-            entries: None,
+            path: self.path.to_owned(),
+            entries: TarReader::new(decoder(f, load)?),
+            pending: None,
+            options: self.options,
+            warnings: Default::default(),
         })
     }
+
+    /// Update the path to the named database in the sync directory unless the path
+    /// was set explicitly
+    fn sync_path(&mut self) {
+        if !self.explicit {
+            let ext = if self.files { "files" } else { "db" };
+            self.path = self.dir.join("sync").join(format!("{}.{}", self.name, ext));
+        }
+    }
+}
+
+/// Database name from the file name with the `.db` or `.files` suffix and any
+/// archive suffixes removed e.g. `core` for `core.db.tar.gz`
+fn db_name(path: &Path) -> Option<String> {
+    let mut name = path.file_name()?.to_string_lossy().to_lowercase();
+    let suffixes: [&[&str]; 3] = [&["gz", "zst", "xz", "bz2"], &["tar"], &["db", "files"]];
+    for exts in &suffixes {
+        if let Some((stem, ext)) = name.rsplit_once('.') {
+            if !stem.is_empty() && exts.contains(&ext) {
+                name = stem.to_owned();
+            }
+        }
+    }
+    Some(name)
 }
 
 /// Use `Database::default()` and the builder pattern to call various `with_` options
@@ -57,75 +165,345 @@ impl Default for Database {
             name: Default::default(),
             dir: PathBuf::from(DEFAULT_DB_DIR),
             path: Default::default(),
+            explicit: false,
+            files: false,
+            options: Default::default(),
+            servers: Default::default(),
+            sig_level: Default::default(),
+        }
+    }
+}
+
+/// `DatabaseSet` is an ordered set of databases. Earlier databases take priority
+/// just like the repository order in `pacman.conf`.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseSet {
+    databases: Vec<Database>,
+}
+impl DatabaseSet {
+    /// Load the databases for the repositories configured in the default
+    /// `/etc/pacman.conf`. To use a different config see `Config::load`.
+    pub fn load() -> Result<Self> {
+        Ok(Config::load(DEFAULT_CONFIG)?.databases())
+    }
+
+    /// Add a database with a lower priority than those already in the set
+    pub fn with_database(mut self, db: Database) -> Self {
+        self.databases.push(db);
+        self
+    }
+
+    /// Get a database by name
+    pub fn get(&self, name: &str) -> Option<&Database> {
+        self.databases.iter().find(|x| x.name == name)
+    }
+
+    /// Iterate over the databases in priority order
+    pub fn iter(&self) -> std::slice::Iter<'_, Database> {
+        self.databases.iter()
+    }
+
+    /// Load an index of each database in priority order e.g. for use with a `Resolver`
+    pub fn index(&self) -> Result<Vec<RepoIndex>> {
+        self.databases.iter().map(RepoIndex::try_from).collect()
+    }
+
+    /// Number of databases in the set
+    pub fn len(&self) -> usize {
+        self.databases.len()
+    }
+
+    /// Check if the set has no databases
+    pub fn is_empty(&self) -> bool {
+        self.databases.is_empty()
+    }
+}
+
+impl FromIterator<Database> for DatabaseSet {
+    fn from_iter<T: IntoIterator<Item = Database>>(iter: T) -> Self {
+        Self {
+            databases: iter.into_iter().collect(),
         }
     }
 }
 
+impl<'a> IntoIterator for &'a DatabaseSet {
+    type Item = &'a Database;
+    type IntoIter = std::slice::Iter<'a, Database>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.databases.iter()
+    }
+}
+
 /// Iterator for Packages
-pub struct Packages<'a> {
+///
+/// Walks the database archive once yielding a parsed `Package` for every package
+/// directory. Entries that fail to parse are yielded as errors without ending the
+/// iteration.
+pub struct Packages {
     path: PathBuf,
-    archive: Option<Archive<GzDecoder<File>>>,
-    entries: Option<Entries<'a, GzDecoder<File>>>,
+    entries: TarReader<Box<dyn Read + Send>>,
+    pending: Option<PackageDir>,
+    options: ParseOptions,
+    warnings: Vec<(String, Error)>, // Problems tolerated by lenient parsing so far
 }
+impl Packages {
+    /// Problems tolerated while parsing the packages yielded so far e.g. missing keys
+    /// or any problem when using `ParseOptions::lenient()` along with the package
+    /// directory e.g. `acl-2.2.53-3`
+    pub fn warnings(&self) -> &[(String, Error)] {
+        &self.warnings
+    }
 
-impl<'a> Iterator for Packages<'a> {
+    fn parse(&mut self, dir: PackageDir) -> Result<Package> {
+        let name = dir.name.to_owned();
+        let (pkg, warnings) = dir.parse(&self.options)?;
+        self.warnings
+            .extend(warnings.into_iter().map(|x| (name.to_owned(), x)));
+        Ok(pkg)
+    }
+}
+
+impl Iterator for Packages {
     type Item = Result<Package>;
 
-    /// Iterator on the tokens
+    /// Iterator on the packages
     fn next(&mut self) -> Option<Self::Item> {
-        // Construct the iterator if needed
-        if self.entries.is_none() {
-            if let Ok(f) = File::open(&self.path) {
-                self.archive = Some(Archive::new(GzDecoder::new(f)));
-            } else {
-                return Some(Err(Error::DatabaseLoad(PathBuf::from(&self.path))));
+        loop {
+            let entry = match self.entries.next() {
+                Some(Ok(x)) => x,
+                Some(Err(e)) => {
+                    return Some(Err(Error::DatabaseIteration(self.path.to_owned(), e)))
+                }
+                None => return self.pending.take().map(|x| self.parse(x)),
+            };
+
+            // Package files are always nested one level deep e.g. `acl-2.2.53-3/desc`
+            let (dir, file) = match entry.path.split_once('/') {
+                Some((dir, file)) if !dir.is_empty() && !file.contains('/') => {
+                    (dir.to_owned(), file.to_owned())
+                }
+                _ => return Some(Err(Error::DatabaseEntry(entry.path))),
+            };
+
+            // Accumulate sibling files until the next package directory starts
+            if let Some(ref mut pkg) = self.pending {
+                if pkg.name == dir {
+                    pkg.push(file, entry);
+                    continue;
+                }
             }
-            // self.entries = Some(self.archive.as_ref().entries());
-            if let Some(&archive) = self.archive {
-                // if let Ok(entries) = archive.entries() {
-                //     self.entries = Some(entries)
-                // }
+            let mut pkg = PackageDir::new(dir);
+            pkg.push(file, entry);
+            if let Some(prev) = self.pending.replace(pkg) {
+                return Some(self.parse(prev));
             }
         }
+    }
+}
 
-        // match self.iter.next() {
-        //     None => None,
-        //     Some(x) => {
-        //         // Parse the package from the iterated tarball file
-        //     },
-        // }
-        None
+/// `PackageDir` collects the files of a single package directory in the archive
+struct PackageDir {
+    name: String,                  // Directory name e.g. `acl-2.2.53-3`
+    files: Vec<(String, Vec<u8>)>, // File names and contents e.g. `desc`
+}
+impl PackageDir {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            files: Default::default(),
+        }
+    }
+
+    fn push(&mut self, file: String, entry: TarEntry) {
+        if entry.kind == EntryType::Regular && !file.is_empty() {
+            self.files.push((file, entry.data));
+        }
+    }
+
+    /// Parse the package from the `desc` file and any sibling files e.g. `depends`.
+    /// The `files` entry of `.files` databases is parsed into the package file list.
+    fn parse(self, options: &ParseOptions) -> Result<(Package, Vec<Error>)> {
+        if !self.files.iter().any(|(name, _)| name == "desc") {
+            return Err(Error::PackageDescMissing(self.name));
+        }
+
+        let mut entries = Vec::new();
+        let mut files = None;
+        let first = self.files.iter().filter(|(name, _)| name == "desc");
+        for (name, data) in first.chain(self.files.iter().filter(|(name, _)| name != "desc")) {
+            let entry = format!("{}/{}", self.name, name);
+            let data = std::str::from_utf8(data)
+                .map_err(|_| Error::PackageUtf8Conversion(entry.to_owned()))?;
+            if name == "files" {
+                files = Some(parse_files(data)?);
+            } else {
+                entries.push((entry, data));
+            }
+        }
+
+        let (mut pkg, warnings) = options.parse_entries(&entries)?;
+        if let Some((files, backup)) = files {
+            pkg.files = files;
+            pkg.backup = backup;
+        }
+        Ok((pkg, warnings))
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "gzip"))]
 mod tests {
     use crate::prelude::*;
-    use ::tar::Archive;
-    use flate2::read::GzDecoder;
-    use std::io::Read;
-    use std::{convert::TryFrom, fs::File};
 
     #[test]
-    fn test_load_community_db() {
-        let pkg_name = "supertux";
-        let f = File::open("tests/sync/community.db").unwrap();
-        let mut archive = Archive::new(GzDecoder::new(f));
-
-        // Decompress target file into memory
-        for mut entry in archive.entries().unwrap().filter_map(|x| x.ok()) {
-            let path = entry.path().unwrap();
-            let path_str = path.to_str().unwrap();
-
-            // Extract target package file as a string
-            if path_str.starts_with(&format!("{}-", pkg_name)) && path_str.ends_with("/desc") {
-                let mut buffer = Vec::new();
-                entry.read_to_end(&mut buffer).unwrap();
-                let desc = std::str::from_utf8(&buffer).unwrap();
-
-                let pkg = Package::try_from(desc).unwrap();
-                println!("{}", pkg);
+    fn test_load_core_db() {
+        let db = Database::default().with_path("tests/sync/core.db");
+        assert_eq!(db.name(), "core");
+
+        let pkgs = db.packages().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(pkgs.len(), 233);
+        assert!(pkgs.iter().all(|x| x.extra.is_empty()));
+
+        let pkg = pkgs.iter().find(|x| x.name == "coreutils").unwrap();
+        assert_eq!(pkg.version, "8.32-1");
+        assert_eq!(pkg.filename, "coreutils-8.32-1-x86_64.pkg.tar.zst");
+        assert_eq!(
+            pkg.depends,
+            vec!["glibc", "acl", "attr", "gmp", "libcap", "openssl"]
+        );
+
+        let pkg = pkgs.iter().find(|x| x.name == "acl").unwrap();
+        assert_eq!(pkg.replaces, vec!["xfsacl"]);
+        assert_eq!(pkg.conflicts, vec!["xfsacl"]);
+        assert_eq!(pkg.provides, vec!["xfsacl", "libacl.so=1-64"]);
+
+        let pkg = pkgs.iter().find(|x| x.name == "bash").unwrap();
+        assert_eq!(pkg.optional_depends.len(), 1);
+        assert_eq!(pkg.optional_depends[0].dependency, "bash-completion");
+        assert_eq!(
+            pkg.optional_depends[0].reason,
+            Some("for tab completion".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_with_path() {
+        let db = Database::default()
+            .with_path("/tmp/x/core.db.tar.gz")
+            .with_files(true)
+            .with_dir("tests");
+        assert_eq!(db.name(), "core");
+        assert_eq!(db.path(), std::path::Path::new("/tmp/x/core.db.tar.gz"));
+
+        let db = Database::default().with_path("tests/sync/core.files");
+        assert_eq!(db.name(), "core");
+        let db = Database::default().with_path("tests/sync/custom.db.tar.zst");
+        assert_eq!(db.name(), "custom");
+        let db = Database::default().with_path("tests/Local.repo");
+        assert_eq!(db.name(), "local.repo");
+
+        // Renaming keeps the explicit path
+        let db = Database::default()
+            .with_path("tests/sync/core.db")
+            .with_name("extra");
+        assert_eq!(db.name(), "extra");
+        assert_eq!(db.path(), std::path::Path::new("tests/sync/core.db"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "zstd", feature = "xz", feature = "bzip2"))]
+    fn test_load_compressed_db() {
+        for codec in &["none", "zstd", "xz", "bzip2"] {
+            let db = Database::default().with_path(format!("tests/compression/{}.db", codec));
+            assert_eq!(db.packages().unwrap().count(), 233, "{}", codec);
+        }
+    }
+
+    #[test]
+    fn test_load_files_db() {
+        let db = Database::default()
+            .with_dir("tests")
+            .with_name("core")
+            .with_files(true);
+        assert_eq!(db.path(), std::path::Path::new("tests/sync/core.files"));
+
+        let pkgs = db
+            .packages()
+            .unwrap()
+            .filter_map(|x| x.ok())
+            .collect::<Vec<_>>();
+        let pkg = pkgs.iter().find(|x| x.name == "coreutils").unwrap();
+        assert_eq!(pkg.version, "8.32-1");
+        assert_eq!(pkg.files.len(), 8);
+        assert_eq!(pkg.files[0], FileEntry::new("usr/"));
+        assert!(pkg.files[0].is_dir());
+        assert!(!pkg.files[3].is_dir());
+
+        // Find which package ships a file
+        let owners = pkgs
+            .iter()
+            .filter(|x| x.owns("/usr/bin/dash"))
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(owners, vec!["dash"]);
+        assert!(pkg.owns("/usr/bin"));
+        assert!(!pkg.owns("/usr/bin/dash"));
+    }
+
+    #[test]
+    fn test_load_with_dir() {
+        let db = Database::default().with_dir("tests").with_name("Core");
+        assert_eq!(db.path(), std::path::Path::new("tests/sync/core.db"));
+        assert!(db.packages().is_ok());
+
+        let db = Database::default().with_dir("tests").with_name("missing");
+        assert!(matches!(db.packages(), Err(Error::DatabaseNotFound(x)) if x == "missing"));
+    }
+
+    #[test]
+    fn test_bad_entries_dont_end_iteration() {
+        // acl has an invalid CSIZE but the iteration continues
+        let db = Database::default().with_path("tests/sync/broken.db");
+        let mut packages = db.packages().unwrap();
+        let err = packages.next().unwrap().unwrap_err();
+        match err {
+            Error::PackageParse(ref x) => {
+                assert_eq!(x.entry, "acl-2.2.53-3/desc");
+                assert_eq!((x.line, x.column), (Some(17), Some(1)));
+                assert_eq!(x.key, "CSIZE");
+                assert_eq!(x.value, Some("13x4".to_owned()));
             }
+            _ => panic!("unexpected error: {}", err),
         }
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.is::<std::num::ParseIntError>());
+        assert_eq!(packages.next().unwrap().unwrap().name, "amd-ucode");
+        assert!(packages.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let db = Database::default().with_path("tests/sync/core.db");
+        let strict = db.to_owned().with_options(ParseOptions::strict());
+        assert_eq!(
+            strict.packages().unwrap().filter(|x| x.is_ok()).count(),
+            233
+        );
+
+        let db = Database::default()
+            .with_path("tests/sync/broken.db")
+            .with_options(ParseOptions::lenient());
+        let mut packages = db.packages().unwrap();
+        let pkg = packages.next().unwrap().unwrap();
+        assert_eq!(pkg.name, "acl");
+        assert_eq!(pkg.size, 0);
+        assert!(matches!(
+            packages.warnings(),
+            [(ref dir, Error::PackageParse(_))] if dir == "acl-2.2.53-3"
+        ));
+        assert!(packages.next().unwrap().is_ok());
+        assert_eq!(packages.warnings().len(), 1);
     }
 }
@@ -0,0 +1,274 @@
+use crate::package::Package;
+use crate::version::Version;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// `Dependency` is a package relation with an optional version constraint e.g.
+/// `glibc>=2.33` or a versioned provision e.g. `libacl.so=1-64`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub constraint: Option<(Op, Version)>,
+}
+
+/// `OptionalDependency` is an optional dependency along with the reason it is
+/// useful e.g. `bash-completion: for tab completion`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct OptionalDependency {
+    pub dependency: Dependency,
+    pub reason: Option<String>,
+}
+
+/// `Op` is the comparison used by a dependency version constraint
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Dependency {
+    /// Check if the given version satisfies the constraint. A dependency without a
+    /// constraint is satisfied by any version.
+    pub fn matches(&self, version: &Version) -> bool {
+        match self.constraint {
+            None => true,
+            Some((op, ref required)) => op.matches(version.vercmp(required)),
+        }
+    }
+
+    /// Check if the package satisfies this dependency either by name or through one
+    /// of its provisions. As with pacman an unversioned provision never satisfies a
+    /// versioned dependency.
+    pub fn satisfied_by(&self, pkg: &Package) -> bool {
+        if pkg.name == self.name && self.matches(&pkg.version) {
+            return true;
+        }
+        pkg.provides
+            .iter()
+            .filter(|x| x.name == self.name)
+            .any(|x| match (&self.constraint, &x.constraint) {
+                (None, _) => true,
+                (Some(_), Some((Op::Eq, version))) => self.matches(version),
+                _ => false,
+            })
+    }
+}
+
+impl Op {
+    /// Check if the result of comparing a version with the required version is allowed
+    pub fn matches(&self, ordering: Ordering) -> bool {
+        match *self {
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl From<&str> for Dependency {
+    /// Split the dependency at the first comparison operator as alpm does
+    fn from(data: &str) -> Self {
+        let i = match data.find(['<', '>', '=']) {
+            Some(i) => i,
+            None => {
+                return Self {
+                    name: data.to_owned(),
+                    constraint: None,
+                }
+            }
+        };
+
+        let rest = &data[i..];
+        let (op, len) = if rest.starts_with(">=") {
+            (Op::Ge, 2)
+        } else if rest.starts_with("<=") {
+            (Op::Le, 2)
+        } else if rest.starts_with('=') {
+            (Op::Eq, 1)
+        } else if rest.starts_with('<') {
+            (Op::Lt, 1)
+        } else {
+            (Op::Gt, 1)
+        };
+
+        Self {
+            name: data[..i].to_owned(),
+            constraint: Some((op, Version::from(&rest[len..]))),
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some((op, ref version)) = self.constraint {
+            write!(f, "{}{}", op, version)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for OptionalDependency {
+    /// Split the reason from the dependency at the first `: ` as alpm does
+    fn from(data: &str) -> Self {
+        let (dep, reason) = match data.split_once(": ") {
+            Some((dep, reason)) => (dep, Some(reason.to_owned())),
+            None => (data, None),
+        };
+        Self {
+            dependency: Dependency::from(dep),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for OptionalDependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.dependency)?;
+        if let Some(ref reason) = self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Op::Lt => write!(f, "<"),
+            Op::Le => write!(f, "<="),
+            Op::Eq => write!(f, "="),
+            Op::Ge => write!(f, ">="),
+            Op::Gt => write!(f, ">"),
+        }
+    }
+}
+
+impl PartialEq<&str> for Dependency {
+    fn eq(&self, other: &&str) -> bool {
+        let other = Dependency::from(*other);
+        self.name == other.name && self.constraint == other.constraint
+    }
+}
+
+/// Dependencies are serialized as text e.g. `glibc>=2.33`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Dependency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Dependency {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Self::from(data.as_str()))
+    }
+}
+
+/// Optional dependencies are serialized as text e.g. `bash: for the helper script`
+#[cfg(feature = "serde")]
+impl serde::Serialize for OptionalDependency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OptionalDependency {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Self::from(data.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Dependency::from("glibc"),
+            Dependency {
+                name: "glibc".to_owned(),
+                constraint: None,
+            }
+        );
+        let cases = [
+            ("glibc<2.33", Op::Lt),
+            ("glibc<=2.33", Op::Le),
+            ("glibc=2.33", Op::Eq),
+            ("glibc>=2.33", Op::Ge),
+            ("glibc>2.33", Op::Gt),
+        ];
+        for &(data, op) in &cases {
+            let dep = Dependency::from(data);
+            assert_eq!(dep.name, "glibc");
+            assert_eq!(dep.constraint, Some((op, Version::from("2.33"))));
+            assert_eq!(dep.to_string(), data);
+        }
+
+        let dep = Dependency::from("libacl.so=1-64");
+        assert_eq!(dep.name, "libacl.so");
+        assert_eq!(dep.constraint, Some((Op::Eq, Version::from("1-64"))));
+        assert_eq!(dep.to_string(), "libacl.so=1-64");
+    }
+
+    #[test]
+    fn test_parse_optional() {
+        let dep = OptionalDependency::from("bash-completion: for tab completion");
+        assert_eq!(dep.dependency, "bash-completion");
+        assert_eq!(dep.reason, Some("for tab completion".to_owned()));
+        assert_eq!(dep.to_string(), "bash-completion: for tab completion");
+
+        let dep = OptionalDependency::from("python>=3: for the bindings: optional");
+        assert_eq!(dep.dependency, "python>=3");
+        assert_eq!(dep.reason, Some("for the bindings: optional".to_owned()));
+
+        let dep = OptionalDependency::from("perl");
+        assert_eq!(dep.dependency, "perl");
+        assert_eq!(dep.reason, None);
+        assert_eq!(dep.to_string(), "perl");
+    }
+
+    #[test]
+    fn test_matches() {
+        let version = Version::from("2.33-4");
+        assert!(Dependency::from("glibc").matches(&version));
+        assert!(Dependency::from("glibc>=2.33").matches(&version));
+        assert!(Dependency::from("glibc=2.33").matches(&version));
+        assert!(Dependency::from("glibc<=2.33-4").matches(&version));
+        assert!(!Dependency::from("glibc<2.33").matches(&version));
+        assert!(!Dependency::from("glibc>2.33-4").matches(&version));
+        assert!(!Dependency::from("glibc>=1:2.0").matches(&version));
+    }
+
+    #[test]
+    fn test_satisfied_by() {
+        let pkg = Package {
+            name: "acl".to_owned(),
+            version: Version::from("2.2.53-3"),
+            provides: vec![
+                Dependency::from("xfsacl"),
+                Dependency::from("libacl.so=1-64"),
+            ],
+            ..Default::default()
+        };
+        assert!(Dependency::from("acl").satisfied_by(&pkg));
+        assert!(Dependency::from("acl>=2.2").satisfied_by(&pkg));
+        assert!(!Dependency::from("acl>2.2.53-3").satisfied_by(&pkg));
+        assert!(Dependency::from("libacl.so").satisfied_by(&pkg));
+        assert!(Dependency::from("libacl.so=1-64").satisfied_by(&pkg));
+        assert!(!Dependency::from("libacl.so>=2").satisfied_by(&pkg));
+        assert!(Dependency::from("xfsacl").satisfied_by(&pkg));
+        assert!(!Dependency::from("xfsacl>=1").satisfied_by(&pkg));
+        assert!(!Dependency::from("attr").satisfied_by(&pkg));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::package::strings;
+use crate::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// `DatabaseDiff` compares two snapshots of the same database e.g. yesterday's and
+/// today's `core.db`. Each list is sorted by package name. Use the `Display` impl for
+/// a changelog e.g. for release notes.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseDiff {
+    pub added: Vec<PackageDiff>,      // Packages only in the new snapshot
+    pub removed: Vec<PackageDiff>,    // Packages only in the old snapshot
+    pub upgraded: Vec<PackageDiff>,   // Packages with a newer version
+    pub downgraded: Vec<PackageDiff>, // Packages with an older version
+    pub modified: Vec<PackageDiff>,   // Packages changed without a version change
+}
+impl DatabaseDiff {
+    /// Compare the packages of the old and new snapshots
+    pub fn between(old: &RepoIndex, new: &RepoIndex) -> Self {
+        let names = old
+            .iter()
+            .chain(new.iter())
+            .map(|x| x.name.as_str())
+            .collect::<BTreeSet<_>>();
+
+        let mut diff = Self::default();
+        for name in names {
+            let (old, new) = (old.get(name).ok(), new.get(name).ok());
+            let x = PackageDiff::new(name, old, new);
+            match (old, new) {
+                (None, _) => diff.added.push(x),
+                (_, None) => diff.removed.push(x),
+                (Some(old), Some(new)) => match new.version.vercmp(&old.version) {
+                    Ordering::Greater => diff.upgraded.push(x),
+                    Ordering::Less => diff.downgraded.push(x),
+                    Ordering::Equal
+                        if new.version != old.version
+                            || x.size_delta != 0
+                            || !x.fields.is_empty() =>
+                    {
+                        diff.modified.push(x)
+                    }
+                    Ordering::Equal => {}
+                },
+            }
+        }
+        diff
+    }
+
+    /// Load and compare two database files e.g. archived copies of `core.db`
+    pub fn load(old: &Database, new: &Database) -> Result<Self> {
+        let old = RepoIndex::try_from(old)?;
+        let new = RepoIndex::try_from(new)?;
+        Ok(Self::between(&old, &new))
+    }
+
+    /// Check if the snapshots have the same packages
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+impl fmt::Display for DatabaseDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sections = [
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+            ("Upgraded", &self.upgraded),
+            ("Downgraded", &self.downgraded),
+            ("Modified", &self.modified),
+        ];
+        for (title, packages) in sections.iter().filter(|x| !x.1.is_empty()) {
+            writeln!(f, "## {}", title)?;
+            for x in packages.iter() {
+                write!(f, "{}", x)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `PackageDiff` is a package that differs between two snapshots
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackageDiff {
+    pub name: String,                 // Name of the package
+    pub old_version: Option<Version>, // Version in the old snapshot if any
+    pub new_version: Option<Version>, // Version in the new snapshot if any
+    pub size_delta: i64,              // Change of the package file size in bytes
+    pub isize_delta: i64,             // Change of the installed size in bytes
+    pub fields: Vec<FieldDiff>,       // Fields that changed, empty if added or removed
+}
+impl PackageDiff {
+    fn new(name: &str, old: Option<&Package>, new: Option<&Package>) -> Self {
+        let sizes = |x: Option<&Package>| x.map(|x| (x.size as i64, x.isize as i64));
+        let (size, isize) = sizes(old).unwrap_or_default();
+        let (new_size, new_isize) = sizes(new).unwrap_or_default();
+        let fields = match (old, new) {
+            (Some(old), Some(new)) => fields(old, new),
+            _ => Default::default(),
+        };
+        Self {
+            name: name.to_owned(),
+            old_version: old.map(|x| x.version.to_owned()),
+            new_version: new.map(|x| x.version.to_owned()),
+            size_delta: new_size - size,
+            isize_delta: new_isize - isize,
+            fields,
+        }
+    }
+
+    /// Get the diff of a field by its database key e.g. `depends`
+    pub fn field(&self, name: &str) -> Option<&FieldDiff> {
+        self.fields.iter().find(|x| x.name == name)
+    }
+}
+
+impl fmt::Display for PackageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "- {}", self.name)?;
+        match (&self.old_version, &self.new_version) {
+            (Some(old), Some(new)) if old != new => write!(f, " {} -> {}", old, new)?,
+            (_, Some(x)) | (Some(x), None) => write!(f, " {}", x)?,
+            (None, None) => {}
+        }
+        if self.old_version.is_some() && self.new_version.is_some() && self.size_delta != 0 {
+            write!(f, " ({:+} bytes)", self.size_delta)?;
+        }
+        writeln!(f)?;
+        for x in &self.fields {
+            writeln!(f, "  - {}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// `FieldDiff` is a field of a package that changed between two snapshots. Single
+/// valued fields e.g. `packager` have their old value removed and new value added.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldDiff {
+    pub name: String,         // Database key in lower case e.g. `depends`
+    pub added: Vec<String>,   // Values only in the new snapshot
+    pub removed: Vec<String>, // Values only in the old snapshot
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.name)?;
+        for x in &self.added {
+            write!(f, " +{}", x)?;
+        }
+        for x in &self.removed {
+            write!(f, " -{}", x)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the fields of two versions of a package in database order
+fn fields(old: &Package, new: &Package) -> Vec<FieldDiff> {
+    let one = |x: &str| match x {
+        "" => vec![],
+        x => vec![x.to_owned()],
+    };
+    let values = |x: &Package| {
+        vec![
+            ("desc", one(&x.desc)),
+            ("groups", x.groups.to_owned()),
+            ("url", one(&x.url)),
+            ("license", x.licenses.to_owned()),
+            ("arch", one(&x.arch)),
+            ("packager", one(&x.packager)),
+            ("replaces", strings(&x.replaces)),
+            ("conflicts", strings(&x.conflicts)),
+            ("provides", strings(&x.provides)),
+            ("depends", strings(&x.depends)),
+            ("optdepends", strings(&x.optional_depends)),
+            ("makedepends", strings(&x.make_depends)),
+            ("checkdepends", strings(&x.check_depends)),
+        ]
+    };
+    values(old)
+        .into_iter()
+        .zip(values(new))
+        .filter_map(|((name, old), (_, new))| {
+            let diff = FieldDiff {
+                name: name.to_owned(),
+                added: new.iter().filter(|x| !old.contains(x)).cloned().collect(),
+                removed: old.iter().filter(|x| !new.contains(x)).cloned().collect(),
+            };
+            Some(diff).filter(|x| !x.added.is_empty() || !x.removed.is_empty())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::package;
+
+    fn snapshots() -> (RepoIndex, RepoIndex) {
+        let mut old = vec![
+            package("acl", "2.2.53-3", &["attr"]),
+            package("bash", "5.0.018-1", &["readline>=7.0", "glibc"]),
+            package("gcc", "10.2.0-4", &[]),
+            package("zlib", "1:1.2.11-4", &[]),
+        ];
+        let mut bash = package("bash", "5.1.0-1", &["readline>=8.0", "glibc"]);
+        bash.size = 1500;
+        let mut zlib = package("zlib", "1:1.2.11-4", &[]);
+        zlib.packager = "Jane Doe <jane@example.com>".to_owned();
+        let mut new = vec![
+            package("acl", "2.2.53-3", &["attr"]),
+            bash,
+            package("gcc", "10.1.0-1", &[]),
+            package("glibc", "2.32-5", &[]),
+            zlib,
+        ];
+        for x in old.iter_mut().chain(new.iter_mut()).filter(|x| x.size == 0) {
+            x.size = 1000;
+        }
+        (old.into_iter().collect(), new.into_iter().collect())
+    }
+
+    #[test]
+    fn test_between() {
+        let (old, new) = snapshots();
+        let diff = DatabaseDiff::between(&old, &new);
+        assert!(!diff.is_empty());
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].name, "glibc");
+        assert_eq!(diff.added[0].old_version, None);
+        assert_eq!(diff.added[0].size_delta, 1000);
+        assert!(diff.removed.is_empty());
+
+        assert_eq!(diff.upgraded.len(), 1);
+        let bash = &diff.upgraded[0];
+        assert_eq!(bash.old_version, Some(Version::from("5.0.018-1")));
+        assert_eq!(bash.new_version, Some(Version::from("5.1.0-1")));
+        assert_eq!(bash.size_delta, 500);
+        assert_eq!(
+            bash.fields,
+            vec![FieldDiff {
+                name: "depends".to_owned(),
+                added: vec!["readline>=8.0".to_owned()],
+                removed: vec!["readline>=7.0".to_owned()],
+            }]
+        );
+        assert!(bash.field("depends").is_some());
+        assert!(bash.field("provides").is_none());
+
+        let names = |x: &[PackageDiff]| x.iter().map(|x| x.name.to_owned()).collect::<Vec<_>>();
+        assert_eq!(names(&diff.downgraded), vec!["gcc"]);
+        assert_eq!(names(&diff.modified), vec!["zlib"]);
+        assert_eq!(diff.modified[0].fields[0].name, "packager");
+
+        let diff = DatabaseDiff::between(&new, &old);
+        assert_eq!(names(&diff.removed), vec!["glibc"]);
+        assert_eq!(diff.removed[0].size_delta, -1000);
+        assert_eq!(names(&diff.upgraded), vec!["gcc"]);
+    }
+
+    #[test]
+    fn test_changelog() {
+        let (old, new) = snapshots();
+        let diff = DatabaseDiff::between(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "## Added\n\
+             - glibc 2.32-5\n\
+             ## Upgraded\n\
+             - bash 5.0.018-1 -> 5.1.0-1 (+500 bytes)\n  \
+               - depends: +readline>=8.0 -readline>=7.0\n\
+             ## Downgraded\n\
+             - gcc 10.2.0-4 -> 10.1.0-1\n\
+             ## Modified\n\
+             - zlib 1:1.2.11-4\n  \
+               - packager: +Jane Doe <jane@example.com>\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_load() {
+        let db = Database::default().with_path("tests/sync/core.db");
+        assert!(DatabaseDiff::load(&db, &db).unwrap().is_empty());
+    }
+}
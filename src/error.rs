@@ -1,49 +1,230 @@
-use std::{error::Error as StdError, fmt, path::PathBuf};
+use crate::compression::Compression;
+use crate::version::Version;
+use std::{error::Error as StdError, fmt, io, path::PathBuf};
 
 /// `Result<T>` provides a simplified result type with a common error type
 pub type Result<T> = std::result::Result<T, Error>;
 
 // An error indicating that something went wrong with an arch linux operation
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    /// An error indicating that iterator construction failed.
-    DatabaseIteration(PathBuf),
+    /// An error indicating that the given package cache directory failed to load.
+    CacheLoad(PathBuf, io::Error),
+
+    /// An error indicating that the archive compression could not be identified.
+    CompressionUnknown,
+
+    /// An error indicating that support for the archive compression wasn't compiled in.
+    CompressionUnsupported(Compression),
+
+    /// An error indicating that the given config file failed to load.
+    ConfigLoad(PathBuf, io::Error),
+
+    /// An error occurred while parsing the given config line.
+    ConfigParse(String),
+
+    /// An error indicating that an unexpected entry was found in the database.
+    DatabaseEntry(String),
+
+    /// An error indicating that reading the database archive failed part way.
+    DatabaseIteration(PathBuf, io::Error),
 
     /// An error indicating that the given database failed to load.
-    DatabaseLoad(PathBuf),
+    DatabaseLoad(PathBuf, io::Error),
 
     /// An error indicating that the given database was not found.
     DatabaseNotFound(String),
 
+    /// An error indicating that writing the given database failed.
+    DatabaseWrite(PathBuf, io::Error),
+
+    /// An error indicating that no package satisfies the dependency, along with the
+    /// chain of packages that required it.
+    DependencyUnsatisfiable(String, Vec<String>),
+
+    /// An error indicating that the given keyring failed to load.
+    KeyringLoad(PathBuf, io::Error),
+
+    /// An error occurred while parsing an mtree keyword.
+    MtreeParse(String),
+
+    /// An error indicating that two packages conflict, along with the chain of
+    /// packages that pulled in the second.
+    PackageConflict(String, String, Vec<String>),
+
+    /// An error indicating that a package directory is missing its desc file.
+    PackageDescMissing(String),
+
+    /// An error indicating that the package would replace the existing version with
+    /// an older one.
+    PackageDowngrade(String, Version, Version),
+
+    /// An error indicating that the given package file failed to load.
+    PackageLoad(PathBuf, io::Error),
+
     /// An error indicating that the given package was not found.
     PackageNotFound(String),
 
-    /// An error occurred while parsing package integers .
-    PackageParseSize,
+    /// An error occurred while parsing a package desc, see `ParseError` for where.
+    PackageParse(Box<ParseError>),
+
+    /// An error indicating that a package property was given more than once.
+    PackagePropertyDuplicate(String),
+
+    /// An error indicating that a package property has an invalid value.
+    PackagePropertyInvalid(String),
 
     /// An error indicating that a required package property is missing.
     PackagePropertyMissing(String),
 
     /// An error occurred during package utf8 conversion after extraction.
     PackageUtf8Conversion(String),
+
+    /// An error indicating that the given search pattern is not a valid regex.
+    SearchPattern(String),
+
+    /// An error indicating that the signature of the given file has expired.
+    SignatureExpired(String),
+
+    /// An error indicating that the signature is not valid for the given file.
+    SignatureInvalid(String),
+
+    /// An error indicating that the signing key with the given fingerprint has expired.
+    SignatureKeyExpired(String),
+
+    /// An error indicating that the signing key with the given fingerprint is revoked.
+    SignatureKeyRevoked(String),
+
+    /// An error indicating that the signing key with the given ID is not in the keyring.
+    SignatureKeyUnknown(String),
+
+    /// An error indicating that the signing key with the given fingerprint is not trusted.
+    SignatureKeyUntrusted(String),
+
+    /// An error indicating that reading the given signed file or signature failed.
+    SignatureLoad(PathBuf, io::Error),
+
+    /// An error indicating that a required signature is missing for the given file.
+    SignatureMissing(String),
+
+    /// An error occurred while decoding OpenPGP signature or key data.
+    SignatureParse(String),
+
+    /// An error indicating that the given `.SRCINFO` failed to load.
+    SrcInfoLoad(PathBuf, io::Error),
+}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::CacheLoad(_, ref err) => Some(err),
+            Error::ConfigLoad(_, ref err) => Some(err),
+            Error::DatabaseIteration(_, ref err) => Some(err),
+            Error::DatabaseLoad(_, ref err) => Some(err),
+            Error::DatabaseWrite(_, ref err) => Some(err),
+            Error::KeyringLoad(_, ref err) => Some(err),
+            Error::PackageLoad(_, ref err) => Some(err),
+            Error::PackageParse(ref err) => Some(err.source.as_ref()),
+            Error::SignatureLoad(_, ref err) => Some(err),
+            Error::SrcInfoLoad(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// `ParseError` locates a problem in a package desc or `.PKGINFO` e.g. `acl-2.2.53-3/desc:17:1` and
+/// keeps the underlying error e.g. a `ParseIntError` as its source
+#[derive(Debug)]
+pub struct ParseError {
+    pub entry: String,         // Entry or file e.g. `acl-2.2.53-3/desc`
+    pub line: Option<usize>,   // Line starting at 1, `None` for a missing key
+    pub column: Option<usize>, // Column starting at 1
+    pub key: String,           // Property key e.g. `CSIZE`
+    pub desc: bool,            // Key is a desc `%KEY%` rather than a `key = value`
+    pub value: Option<String>, // Offending value as given
+    pub source: Box<dyn StdError + Send + Sync>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.entry.is_empty() {
+            write!(f, "{}:", self.entry)?;
+        }
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "{}:{}:", line, column)?;
+        }
+        if !self.key.is_empty() {
+            if self.desc {
+                write!(f, " %{}%", self.key)?;
+            } else {
+                write!(f, " {}", self.key)?;
+            }
+        }
+        if let Some(ref value) = self.value {
+            write!(f, " {:?}", value)?;
+        }
+        write!(f, ": {}", self.source)
+    }
 }
-impl StdError for Error {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::DatabaseIteration(ref path) => write!(
+            Error::CacheLoad(ref path, ref err) => {
+                write!(
+                    f,
+                    "failed to load package cache: {}: {}",
+                    path.display(),
+                    err
+                )
+            }
+            Error::CompressionUnknown => write!(f, "failed to identify archive compression"),
+            Error::CompressionUnsupported(ref codec) => {
+                write!(f, "unsupported archive compression: {}", codec)
+            }
+            Error::ConfigLoad(ref path, ref err) => {
+                write!(f, "failed to load config: {}: {}", path.display(), err)
+            }
+            Error::ConfigParse(ref line) => write!(f, "config parse failure: {}", line),
+            Error::DatabaseEntry(ref entry) => write!(f, "unexpected database entry: {}", entry),
+            Error::DatabaseIteration(ref path, ref err) => {
+                write!(f, "failed to read database: {}: {}", path.display(), err)
+            }
+            Error::DatabaseLoad(ref path, ref err) => {
+                write!(f, "failed to load database: {}: {}", path.display(), err)
+            }
+            Error::DependencyUnsatisfiable(ref dep, ref chain) => {
+                write!(f, "unsatisfiable dependency: {}", dep)?;
+                write_chain(f, chain)
+            }
+            Error::DatabaseWrite(ref path, ref err) => {
+                write!(f, "failed to write database: {}: {}", path.display(), err)
+            }
+            Error::DatabaseNotFound(ref db) => write!(f, "failed to find database: {}", db),
+            Error::KeyringLoad(ref path, ref err) => {
+                write!(f, "failed to load keyring: {}: {}", path.display(), err)
+            }
+            Error::MtreeParse(ref keyword) => write!(f, "mtree parse failure: {}", keyword),
+            Error::PackageConflict(ref pkg, ref other, ref chain) => {
+                write!(f, "package conflict: {} and {}", pkg, other)?;
+                write_chain(f, chain)
+            }
+            Error::PackageDescMissing(ref pkg) => write!(f, "package desc missing: {}", pkg),
+            Error::PackageDowngrade(ref pkg, ref existing, ref version) => write!(
                 f,
-                "failed to construct database iterator: {}",
-                path.display()
+                "package downgrade refused: {} {} to {}",
+                pkg, existing, version
             ),
-            Error::DatabaseLoad(ref path) => {
-                write!(f, "failed to load database: {}", path.display())
+            Error::PackageLoad(ref path, ref err) => {
+                write!(f, "failed to load package: {}: {}", path.display(), err)
             }
-            Error::DatabaseNotFound(ref db) => write!(f, "failed to find database: {}", db),
             Error::PackageNotFound(ref pkg) => write!(f, "failed to find package: {}", pkg),
-            Error::PackageParseSize => {
-                write!(f, "package parse failure while parsing integers")
+            Error::PackageParse(ref err) => write!(f, "package parse failure: {}", err),
+            Error::PackagePropertyDuplicate(ref prop) => {
+                write!(f, "package property duplicate: {}", prop)
+            }
+            Error::PackagePropertyInvalid(ref value) => {
+                write!(f, "package property invalid: {}", value)
             }
             Error::PackagePropertyMissing(ref prop) => {
                 write!(f, "package property missing: {}", prop)
@@ -53,6 +234,31 @@ impl fmt::Display for Error {
                 "package utf8 conversion failed after extraction: {}",
                 pkg
             ),
+            Error::SearchPattern(ref msg) => write!(f, "invalid search pattern: {}", msg),
+            Error::SignatureExpired(ref file) => write!(f, "signature expired: {}", file),
+            Error::SignatureInvalid(ref file) => write!(f, "invalid signature: {}", file),
+            Error::SignatureKeyExpired(ref key) => write!(f, "signing key expired: {}", key),
+            Error::SignatureKeyRevoked(ref key) => write!(f, "signing key revoked: {}", key),
+            Error::SignatureKeyUnknown(ref key) => write!(f, "unknown signing key: {}", key),
+            Error::SignatureKeyUntrusted(ref key) => {
+                write!(f, "signing key not trusted: {}", key)
+            }
+            Error::SignatureLoad(ref path, ref err) => {
+                write!(f, "failed to read signed file: {}: {}", path.display(), err)
+            }
+            Error::SignatureMissing(ref file) => write!(f, "missing required signature: {}", file),
+            Error::SignatureParse(ref msg) => write!(f, "signature parse failure: {}", msg),
+            Error::SrcInfoLoad(ref path, ref err) => {
+                write!(f, "failed to load srcinfo: {}: {}", path.display(), err)
+            }
         }
     }
 }
+
+/// Write the chain of packages that required a dependency if any
+fn write_chain(f: &mut fmt::Formatter, chain: &[String]) -> fmt::Result {
+    if chain.is_empty() {
+        return Ok(());
+    }
+    write!(f, " (required by {})", chain.join(" -> "))
+}
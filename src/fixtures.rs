@@ -0,0 +1,36 @@
+use crate::prelude::*;
+
+/// `%PGPSIG%` of `tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst` by the packager key in
+/// `tests/pgp/keyring`
+pub(crate) const PGPSIG: &str =
+    "iHUEABYIAB0WIQRXdLC470enrtVZjT6VsmsVYl9JkQUCX/ZPAAAKCRCVsmsVYl9Jka\
+    AsAP9EVgEPpZUGI9cID0RVCsPFqIUcx6n6d58ZoXfMY4edtQEA2cmdCNBPbm1MOPmnc8H0tMqPa25e40XUB\
+    UAI5q9CSAk=";
+
+/// Create a package with the given name, version and runtime dependencies
+pub(crate) fn package(name: &str, version: &str, depends: &[&str]) -> Package {
+    Package {
+        name: name.to_owned(),
+        version: Version::from(version),
+        depends: depends.iter().map(|x| Dependency::from(*x)).collect(),
+        ..Default::default()
+    }
+}
+
+/// Create a named repository of the given packages
+pub(crate) fn repo(name: &str, packages: Vec<Package>) -> RepoIndex {
+    packages.into_iter().collect::<RepoIndex>().with_name(name)
+}
+
+/// Database entry of `tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst` as `repo-add` writes it
+pub(crate) fn foo() -> Package {
+    Package {
+        filename: "foo-1.0-1-x86_64.pkg.tar.zst".to_owned(),
+        size: 1708,
+        md5sum: "47271b4b3c729b3b7b805be5e43c6f67".to_owned(),
+        sha256sum: "ae17cbd10c2af2c353f3d1ddbfda50aee8907d2ffb752b12e1bb09108007a84c".to_owned(),
+        packager: "Jane Doe <jane@example.com>".to_owned(),
+        pgpsig: PGPSIG.to_owned(),
+        ..package("foo", "1.0-1", &["glibc", "sh"])
+    }
+}
@@ -0,0 +1,213 @@
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+
+/// `RepoIndex` holds the packages of a repository in memory indexed by name, by the
+/// names they provide e.g. `sh` or `libacl.so` and by group.
+#[derive(Debug, Default, Clone)]
+pub struct RepoIndex {
+    name: String,                          // Name of the repository e.g. `core`
+    packages: Vec<Package>,                // Packages in database order
+    names: HashMap<String, usize>,         // Package name to package
+    provides: HashMap<String, Vec<usize>>, // Provided name to providing packages
+    groups: HashMap<String, Vec<usize>>,   // Group name to member packages
+}
+impl RepoIndex {
+    /// Set the name of the repository e.g. `core`
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Add a package to the index replacing any existing package of the same name
+    pub fn insert(&mut self, pkg: Package) {
+        if let Some(&i) = self.names.get(&pkg.name) {
+            self.remove_links(i);
+            self.packages[i] = pkg;
+            self.add_links(i);
+        } else {
+            self.packages.push(pkg);
+            self.add_links(self.packages.len() - 1);
+        }
+    }
+
+    /// Name of the repository e.g. `core`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get a package by name
+    pub fn get(&self, name: &str) -> Result<&Package> {
+        self.names
+            .get(name)
+            .map(|&i| &self.packages[i])
+            .ok_or_else(|| Error::PackageNotFound(name.to_owned()))
+    }
+
+    /// Get the packages providing the given name e.g. `sh` or `libacl.so`. Only
+    /// explicit provisions are considered, use `satisfier` to include package names.
+    pub fn provides(&self, name: &str) -> Result<Vec<&Package>> {
+        self.lookup(&self.provides, name)
+    }
+
+    /// Get the packages in the given group e.g. `base-devel`
+    pub fn group(&self, name: &str) -> Result<Vec<&Package>> {
+        self.lookup(&self.groups, name)
+    }
+
+    /// Find the package satisfying the dependency preferring a package of the same
+    /// name over one providing it, as pacman does.
+    pub fn satisfier(&self, dep: &Dependency) -> Result<&Package> {
+        let by_name = self.get(&dep.name).ok().filter(|x| dep.satisfied_by(x));
+        by_name
+            .or_else(|| {
+                self.provides
+                    .get(&dep.name)
+                    .into_iter()
+                    .flatten()
+                    .map(|&i| &self.packages[i])
+                    .find(|x| dep.satisfied_by(x))
+            })
+            .ok_or_else(|| Error::PackageNotFound(dep.to_string()))
+    }
+
+    /// Iterate over the packages in database order
+    pub fn iter(&self) -> std::slice::Iter<'_, Package> {
+        self.packages.iter()
+    }
+
+    /// Number of packages in the index
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    /// Check if the index has no packages
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    fn lookup(&self, map: &HashMap<String, Vec<usize>>, name: &str) -> Result<Vec<&Package>> {
+        map.get(name)
+            .filter(|x| !x.is_empty())
+            .map(|x| x.iter().map(|&i| &self.packages[i]).collect())
+            .ok_or_else(|| Error::PackageNotFound(name.to_owned()))
+    }
+
+    fn add_links(&mut self, i: usize) {
+        let pkg = &self.packages[i];
+        self.names.insert(pkg.name.to_owned(), i);
+        for x in &pkg.provides {
+            self.provides.entry(x.name.to_owned()).or_default().push(i);
+        }
+        for x in &pkg.groups {
+            self.groups.entry(x.to_owned()).or_default().push(i);
+        }
+    }
+
+    fn remove_links(&mut self, i: usize) {
+        for x in self.provides.values_mut().chain(self.groups.values_mut()) {
+            x.retain(|&x| x != i);
+        }
+    }
+}
+
+impl FromIterator<Package> for RepoIndex {
+    fn from_iter<T: IntoIterator<Item = Package>>(iter: T) -> Self {
+        let mut index = Self::default();
+        for pkg in iter {
+            index.insert(pkg);
+        }
+        index
+    }
+}
+
+impl TryFrom<&Database> for RepoIndex {
+    type Error = Error;
+
+    /// Index all packages of the database failing on the first bad entry
+    fn try_from(db: &Database) -> Result<Self> {
+        let index = db.packages()?.collect::<Result<RepoIndex>>()?;
+        Ok(index.with_name(db.name()))
+    }
+}
+
+impl<'a> IntoIterator for &'a RepoIndex {
+    type Item = &'a Package;
+    type IntoIter = std::slice::Iter<'a, Package>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packages.iter()
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use super::*;
+
+    fn core() -> RepoIndex {
+        let db = Database::default().with_path("tests/sync/core.db");
+        db.packages()
+            .unwrap()
+            .collect::<Result<RepoIndex>>()
+            .unwrap()
+            .with_name(db.name())
+    }
+
+    #[test]
+    fn test_get() {
+        let index = core();
+        assert_eq!(index.name(), "core");
+        assert_eq!(index.get("coreutils").unwrap().version, "8.32-1");
+        assert!(matches!(index.get("missing"), Err(Error::PackageNotFound(x)) if x == "missing"));
+    }
+
+    #[test]
+    fn test_provides() {
+        let index = core();
+        let names = |x: Vec<&Package>| x.iter().map(|x| x.name.to_owned()).collect::<Vec<_>>();
+        assert_eq!(names(index.provides("libbz2.so").unwrap()), vec!["bzip2"]);
+        assert_eq!(
+            names(index.provides("libext2fs.so").unwrap()),
+            vec!["e2fsprogs"]
+        );
+        assert!(index.provides("coreutils").is_err());
+
+        let dep = Dependency::from("libbz2.so=1.0-64");
+        assert_eq!(index.satisfier(&dep).unwrap().name, "bzip2");
+        let dep = Dependency::from("coreutils>=8");
+        assert_eq!(index.satisfier(&dep).unwrap().name, "coreutils");
+        let dep = Dependency::from("coreutils>=9");
+        assert!(
+            matches!(index.satisfier(&dep), Err(Error::PackageNotFound(x)) if x == "coreutils>=9")
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let index = core();
+        let group = index.group("base-devel").unwrap();
+        assert!(group.iter().any(|x| x.name == "autoconf"));
+        assert!(group
+            .iter()
+            .all(|x| x.groups.contains(&"base-devel".to_owned())));
+        assert!(matches!(
+            index.group("missing"),
+            Err(Error::PackageNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut index = core();
+        let len = index.len();
+        let mut pkg = index.get("bzip2").unwrap().clone();
+        pkg.version = Version::from("1.0.8-5");
+        pkg.provides.clear();
+        index.insert(pkg);
+
+        assert_eq!(index.len(), len);
+        assert_eq!(index.get("bzip2").unwrap().version, "1.0.8-5");
+        assert!(index.provides("libbz2.so").is_err());
+    }
+}
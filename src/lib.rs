@@ -1,7 +1,31 @@
+pub mod compression;
+pub mod config;
+#[cfg(feature = "serde")]
+pub mod date;
 pub mod db;
+pub mod dependency;
+pub mod diff;
 pub mod error;
+#[cfg(test)]
+mod fixtures;
+pub mod index;
+pub mod local;
+pub mod mtree;
 pub mod package;
 pub mod parser;
+#[cfg(feature = "pgp")]
+pub mod pgp;
+pub mod pkgfile;
+pub mod resolver;
+pub mod reverse;
+pub mod search;
+pub mod signature;
+pub mod srcinfo;
+mod tar;
+pub mod upgrade;
+pub mod verify;
+pub mod version;
+pub mod writer;
 
 /// All essential symbols in a simple consumable way
 ///
@@ -10,5 +34,32 @@ pub mod parser;
 /// use package_iter::prelude::*;
 /// ```
 pub mod prelude {
-    pub use crate::{db::*, error::*, package::*};
+    pub use crate::{
+        compression::*,
+        config::*,
+        db::*,
+        dependency::*,
+        diff::*,
+        error::*,
+        index::*,
+        local::*,
+        mtree::{MtreeEntry, MtreeKind},
+        package::*,
+        parser::{ParseMode, ParseOptions},
+        pkgfile::*,
+        resolver::*,
+        reverse::*,
+        search::*,
+        signature::*,
+        srcinfo::*,
+        upgrade::*,
+        verify::*,
+        version::*,
+        writer::*,
+    };
+
+    #[cfg(feature = "serde")]
+    pub use crate::date::{DateFormat, Dated};
+    #[cfg(feature = "pgp")]
+    pub use crate::pgp::*;
 }
@@ -0,0 +1,291 @@
+use crate::compression::decoder;
+use crate::db::DEFAULT_DB_DIR;
+use crate::parser::parse_files;
+use crate::prelude::*;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+/// `LocalDatabase` provides access to the installed packages recorded by pacman
+pub struct LocalDatabase {
+    dir: PathBuf,          // Directory to search for databases e.g. `/var/lib/pacman`
+    options: ParseOptions, // Validation applied to each package desc
+}
+impl LocalDatabase {
+    /// Set the directory to search for the local database
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Set the validation applied to each package e.g. `ParseOptions::strict()`
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Load the local database using the default directory. To control where the
+    /// database is loaded from use `LocalDatabase::default()` and the builder pattern
+    /// to call `with_` functions to set the desired properties.
+    ///
+    /// Default: loads from /var/lib/pacman/local
+    pub fn load() -> Result<Self> {
+        let db = Self::default();
+
+        // Validate the database exists
+        if !db.path().is_dir() {
+            return Err(Error::DatabaseNotFound("local".to_owned()));
+        }
+
+        Ok(db)
+    }
+
+    /// Full path to the local database directory e.g. `/var/lib/pacman/local`
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("local")
+    }
+
+    /// Construct an iterator over the installed packages
+    pub fn packages(&self) -> Result<LocalPackages> {
+        let path = self.path();
+        if !path.is_dir() {
+            return Err(Error::DatabaseNotFound("local".to_owned()));
+        }
+
+        // Each installed package is a `<name>-<version>` directory
+        let mut dirs = fs::read_dir(&path)
+            .map_err(|e| Error::DatabaseLoad(path.to_owned(), e))?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| x.is_dir())
+            .collect::<Vec<_>>();
+        dirs.sort();
+
+        Ok(LocalPackages {
+            dirs: dirs.into_iter(),
+            options: self.options,
+            warnings: Default::default(),
+        })
+    }
+}
+
+/// Use `LocalDatabase::default()` and the builder pattern to call various `with_`
+/// options to control the exact database initialization desired.
+impl Default for LocalDatabase {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_DB_DIR),
+            options: Default::default(),
+        }
+    }
+}
+
+/// Iterator for installed packages
+pub struct LocalPackages {
+    dirs: std::vec::IntoIter<PathBuf>,
+    options: ParseOptions,
+    warnings: Vec<(String, Error)>, // Problems tolerated by lenient parsing so far
+}
+impl LocalPackages {
+    /// Problems tolerated while parsing the packages yielded so far e.g. missing keys
+    /// or any problem when using `ParseOptions::lenient()` along with the package
+    /// directory e.g. `gmp-6.2.0-1`. Mtree files skipped for lack of a compression codec
+    /// are included in every mode.
+    pub fn warnings(&self) -> &[(String, Error)] {
+        &self.warnings
+    }
+}
+
+impl Iterator for LocalPackages {
+    type Item = Result<InstalledPackage>;
+
+    /// Iterator on the installed packages
+    fn next(&mut self) -> Option<Self::Item> {
+        let dir = self.dirs.next()?;
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let (pkg, warnings) = match load_package(&dir, &self.options) {
+            Ok(x) => x,
+            Err(e) => return Some(Err(e)),
+        };
+        self.warnings
+            .extend(warnings.into_iter().map(|x| (name.to_string(), x)));
+        Some(Ok(pkg))
+    }
+}
+
+/// Load an installed package from its `desc`, `files` and `mtree` files along with
+/// the problems tolerated while parsing the `desc`
+fn load_package(dir: &Path, options: &ParseOptions) -> Result<(InstalledPackage, Vec<Error>)> {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    let path = dir.join("desc");
+    let desc = read_file(&path)?.ok_or_else(|| Error::PackageDescMissing(name.to_string()))?;
+    let (mut pkg, mut warnings) = options.parse_installed(&path.display().to_string(), &desc)?;
+
+    if let Some(files) = read_file(&dir.join("files"))? {
+        let (files, backup) = parse_files(&files)?;
+        pkg.package.files = files;
+        pkg.package.backup = backup;
+    }
+
+    // The mtree is always compressed so without the codec it is skipped with a warning
+    match read_file(&dir.join("mtree")) {
+        Ok(Some(mtree)) => pkg.mtree = crate::mtree::parse(&mtree)?,
+        Ok(None) => (),
+        Err(e @ Error::CompressionUnsupported(_)) => warnings.push(e),
+        Err(e) => return Err(e),
+    }
+
+    Ok((pkg, warnings))
+}
+
+/// Read a possibly compressed file into a string, `None` when it doesn't exist
+fn read_file(path: &Path) -> Result<Option<String>> {
+    let load = |e| Error::DatabaseLoad(path.to_owned(), e);
+    let f = match File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(load(e)),
+    };
+
+    let mut data = Vec::new();
+    let mut reader: Box<dyn Read> = match path.file_name() {
+        Some(x) if x == "mtree" => decoder(f, load)?,
+        _ => Box::new(f),
+    };
+    reader.read_to_end(&mut data).map_err(load)?;
+
+    String::from_utf8(data)
+        .map(Some)
+        .map_err(|_| Error::PackageUtf8Conversion(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_load_local_db() {
+        let db = LocalDatabase::default().with_dir("tests");
+        let pkgs = db.packages().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(pkgs.len(), 3);
+
+        let pkg = &pkgs[0];
+        assert_eq!(pkg.package.name, "coreutils");
+        assert_eq!(pkg.package.version, "8.31-3");
+        assert_eq!(pkg.package.isize, 16533456);
+        assert_eq!(pkg.install_date, 1574100000);
+        assert_eq!(pkg.reason, InstallReason::Dependency);
+        assert_eq!(pkg.validation, vec![Validation::Signature]);
+        assert_eq!(pkg.package.files.len(), 8);
+        assert!(pkg.package.files[1].is_dir());
+        assert!(!pkg.package.files[2].is_dir());
+        assert_eq!(
+            pkg.package.files[7].path,
+            "usr/share/licenses/coreutils/my license"
+        );
+
+        assert_eq!(pkg.mtree.len(), 11);
+        assert_eq!(pkg.mtree[5].link, Some("ls".to_owned()));
+        assert_eq!(
+            pkg.mtree[10].path,
+            "usr/share/licenses/coreutils/my license"
+        );
+
+        let pkg = &pkgs[2];
+        assert_eq!(pkg.package.name, "yay");
+        assert_eq!(pkg.reason, InstallReason::Explicit);
+        assert_eq!(pkg.xdata, vec![("pkgtype".to_owned(), "pkg".to_owned())]);
+        assert_eq!(
+            pkg.package.backup,
+            vec![Backup {
+                path: "etc/yay.conf".to_owned(),
+                md5sum: "d41d8cd98f00b204e9800998ecf8427e".to_owned(),
+            }]
+        );
+        assert!(pkg.mtree.is_empty());
+    }
+
+    #[test]
+    fn test_mtree_codec() {
+        let db = LocalDatabase::default().with_dir("tests");
+        let mut pkgs = db.packages().unwrap();
+        let pkg = pkgs.next().unwrap().unwrap();
+        assert_eq!(pkg.package.name, "coreutils");
+        assert_eq!(pkg.package.files.len(), 8);
+
+        // Without gzip support the mtree is skipped rather than failing the package
+        if cfg!(feature = "gzip") {
+            assert_eq!(pkg.mtree.len(), 11);
+            assert!(pkgs.warnings().is_empty());
+        } else {
+            assert!(pkg.mtree.is_empty());
+            assert_eq!(pkgs.warnings().len(), 1);
+            assert_eq!(pkgs.warnings()[0].0, "coreutils-8.31-3");
+            assert!(matches!(
+                pkgs.warnings()[0].1,
+                Error::CompressionUnsupported(Compression::Gzip)
+            ));
+        }
+        assert_eq!(pkgs.count(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_compare_with_sync() {
+        let sync = Database::default()
+            .with_dir("tests")
+            .with_name("core")
+            .packages()
+            .unwrap()
+            .filter_map(|x| x.ok())
+            .find(|x| x.name == "gmp")
+            .unwrap();
+        let local = LocalDatabase::default()
+            .with_dir("tests")
+            .packages()
+            .unwrap()
+            .filter_map(|x| x.ok())
+            .find(|x| x.package.name == "gmp")
+            .unwrap();
+        assert_eq!(local.package.version, sync.version);
+        assert_eq!(local.package.isize, sync.isize);
+        assert_eq!(local.package.depends, sync.depends);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_parse_strict() {
+        let db = LocalDatabase::default()
+            .with_dir("tests")
+            .with_options(ParseOptions::strict());
+        assert_eq!(db.packages().unwrap().filter(|x| x.is_ok()).count(), 3);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg = dir.path().join("local/foo-1.0-1");
+        std::fs::create_dir_all(&pkg).unwrap();
+        std::fs::write(pkg.join("desc"), "%NAME%\nfoo\n\n%SIZE%\nbig\n").unwrap();
+        let db = LocalDatabase::default().with_dir(dir.path());
+        assert!(db.packages().unwrap().next().unwrap().is_err());
+
+        let db = db.with_options(ParseOptions::lenient());
+        let mut packages = db.packages().unwrap();
+        assert_eq!(packages.next().unwrap().unwrap().package.name, "foo");
+        let dirs = packages
+            .warnings()
+            .iter()
+            .map(|x| x.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(dirs.len(), 7);
+        assert!(dirs.iter().all(|x| *x == "foo-1.0-1"));
+    }
+
+    #[test]
+    fn test_missing_local_db() {
+        let db = LocalDatabase::default().with_dir("tests/missing");
+        assert!(matches!(db.packages(), Err(Error::DatabaseNotFound(_))));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::error::*;
+
+/// `MtreeEntry` describes a single file recorded in a package `mtree`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MtreeEntry {
+    pub path: String,
+    pub kind: MtreeKind,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub size: u64,
+    pub time: String,
+    pub link: Option<String>,
+    pub md5digest: Option<String>,
+    pub sha256digest: Option<String>,
+}
+
+/// `MtreeKind` is the type of a file recorded in a package `mtree`
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MtreeKind {
+    #[default]
+    File,
+    Dir,
+    Link,
+}
+
+/// Parse the decompressed text of an `mtree` file. Defaults given with `/set` are
+/// applied to each following entry until changed or cleared with `/unset`.
+pub fn parse(data: &str) -> Result<Vec<MtreeEntry>> {
+    let mut entries = Vec::new();
+    let mut defaults = MtreeEntry::default();
+
+    for line in data.lines().map(|x| x.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("/set") => {
+                for x in fields {
+                    keyword(&mut defaults, x)?;
+                }
+            }
+            Some("/unset") => {
+                for x in fields {
+                    if x == "all" {
+                        defaults = MtreeEntry::default();
+                    } else {
+                        keyword(&mut defaults, &format!("{}=", x)).ok();
+                    }
+                }
+            }
+            Some(path) => {
+                let mut entry = MtreeEntry {
+                    path: unescape(path.trim_start_matches("./")),
+                    ..defaults.clone()
+                };
+                for x in fields {
+                    keyword(&mut entry, x)?;
+                }
+                entries.push(entry);
+            }
+            None => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Apply a single `key=value` keyword to the entry. An empty value resets the field.
+fn keyword(entry: &mut MtreeEntry, data: &str) -> Result<()> {
+    let invalid = || Error::MtreeParse(data.to_owned());
+    let (key, value) = data.split_once('=').ok_or_else(invalid)?;
+
+    match key {
+        "type" => {
+            entry.kind = match value {
+                "file" | "" => MtreeKind::File,
+                "dir" => MtreeKind::Dir,
+                "link" => MtreeKind::Link,
+                _ => return Err(invalid()),
+            }
+        }
+        "uid" if value.is_empty() => entry.uid = 0,
+        "uid" => entry.uid = value.parse().map_err(|_| invalid())?,
+        "gid" if value.is_empty() => entry.gid = 0,
+        "gid" => entry.gid = value.parse().map_err(|_| invalid())?,
+        "mode" if value.is_empty() => entry.mode = 0,
+        "mode" => entry.mode = u32::from_str_radix(value, 8).map_err(|_| invalid())?,
+        "size" if value.is_empty() => entry.size = 0,
+        "size" => entry.size = value.parse().map_err(|_| invalid())?,
+        "time" => entry.time = value.to_owned(),
+        "link" => entry.link = Some(unescape(value)).filter(|x| !x.is_empty()),
+        "md5digest" => entry.md5digest = Some(value.to_owned()).filter(|x| !x.is_empty()),
+        "sha256digest" => entry.sha256digest = Some(value.to_owned()).filter(|x| !x.is_empty()),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Decode the octal `\ooo` escapes used for special characters in mtree paths
+fn unescape(data: &str) -> String {
+    let bytes = data.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|x| x.iter().all(|x| (b'0'..=b'7').contains(x)));
+        match octal {
+            Some(x) if bytes[i] == b'\\' => {
+                result.push(
+                    x.iter()
+                        .fold(0u8, |acc, x| acc.wrapping_mul(8) + (x - b'0')),
+                );
+                i += 4;
+            }
+            _ => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse(
+            "#mtree\n/set type=file uid=0 gid=0 mode=644\n\
+             ./.PKGINFO time=1573649720.0 size=612 md5digest=abc\n\
+             /set mode=755\n\
+             ./usr time=1573649720.0 type=dir\n\
+             ./usr/bin/dir time=1573649720.0 mode=777 type=link link=ls\n\
+             /unset mode\n\
+             ./usr/my\\040file size=1\n",
+        )
+        .unwrap();
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[0],
+            MtreeEntry {
+                path: ".PKGINFO".to_owned(),
+                mode: 0o644,
+                size: 612,
+                time: "1573649720.0".to_owned(),
+                md5digest: Some("abc".to_owned()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(entries[1].kind, MtreeKind::Dir);
+        assert_eq!(entries[1].mode, 0o755);
+        assert_eq!(entries[2].kind, MtreeKind::Link);
+        assert_eq!(entries[2].mode, 0o777);
+        assert_eq!(entries[2].link, Some("ls".to_owned()));
+        assert_eq!(entries[3].path, "usr/my file");
+        assert_eq!(entries[3].mode, 0);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(parse("./foo mode=999\n"), Err(Error::MtreeParse(x)) if x == "mode=999"));
+        assert!(matches!(
+            parse("./foo type=fifo\n"),
+            Err(Error::MtreeParse(_))
+        ));
+        assert!(matches!(parse("./foo uid=root\n"), Err(Error::MtreeParse(x)) if x == "uid=root"));
+        assert!(matches!(parse("./foo gid=-1\n"), Err(Error::MtreeParse(x)) if x == "gid=-1"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("foo\\040bar"), "foo bar");
+        assert_eq!(unescape("foo\\bar"), "foo\\bar");
+        assert_eq!(unescape("\\303\\251t\\303\\251"), "été");
+    }
+}
@@ -1,12 +1,17 @@
+use crate::dependency::{Dependency, OptionalDependency};
+use crate::mtree::MtreeEntry;
+use crate::version::Version;
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Package {
     pub name: String,
     pub base: String,
     pub filename: String,
-    pub version: String,
+    pub version: Version,
     pub desc: String,
+    pub groups: Vec<String>,
     pub url: String,
     pub size: u64,
     pub isize: u64,
@@ -14,42 +19,31 @@ pub struct Package {
     pub md5sum: String,
     pub sha256sum: String,
     pub pgpsig: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::date::text"))]
     pub build_date: String,
     pub packager: String,
     pub licenses: Vec<String>,
-    pub provides: Vec<String>,
-    pub depends: Vec<String>,
-    pub make_depends: Vec<String>,
-    pub optional_depends: Vec<String>,
-    pub check_depends: Vec<String>,
+    pub replaces: Vec<Dependency>,
+    pub conflicts: Vec<Dependency>,
+    pub provides: Vec<Dependency>,
+    pub depends: Vec<Dependency>,
+    pub make_depends: Vec<Dependency>,
+    pub optional_depends: Vec<OptionalDependency>,
+    pub check_depends: Vec<Dependency>,
+    pub files: Vec<FileEntry>, // Only available from `.files` databases or the local database
+    pub backup: Vec<Backup>,   // Only available from the local database
+    pub extra: Vec<(String, Vec<String>)>, // Unknown keys in the order given
 }
-impl Default for Package {
-    fn default() -> Self {
-        Self {
-            name: Default::default(),
-            base: Default::default(),
-            filename: Default::default(),
-            version: Default::default(),
-            desc: Default::default(),
-            url: Default::default(),
-            size: Default::default(),
-            isize: Default::default(),
-            arch: Default::default(),
-            md5sum: Default::default(),
-            sha256sum: Default::default(),
-            pgpsig: Default::default(),
-            build_date: Default::default(),
-            packager: Default::default(),
-            licenses: Default::default(),
-            provides: Default::default(),
-            depends: Default::default(),
-            make_depends: Default::default(),
-            optional_depends: Default::default(),
-            check_depends: Default::default(),
-        }
+impl Package {
+    /// Check if the package ships the given file e.g. `/usr/bin/ls`. File lists are
+    /// only available from `.files` databases or the local database.
+    pub fn owns(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        self.files
+            .iter()
+            .any(|x| x.path == path || x.path.trim_end_matches('/') == path)
     }
 }
-
 impl fmt::Display for Package {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "Name: {}", self.name)?;
@@ -57,6 +51,7 @@ impl fmt::Display for Package {
         writeln!(f, "Filename: {}", self.filename)?;
         writeln!(f, "Version: {}", self.version)?;
         writeln!(f, "Desc: {}", self.desc)?;
+        writeln!(f, "Groups: {:?}", self.groups)?;
         writeln!(f, "URL: {}", self.url)?;
         writeln!(f, "Size: {}", self.size)?;
         writeln!(f, "ISize: {}", self.isize)?;
@@ -67,11 +62,78 @@ impl fmt::Display for Package {
         writeln!(f, "Build Date: {}", self.build_date)?;
         writeln!(f, "Packager: {}", self.packager)?;
         writeln!(f, "Licenses: {:?}", self.licenses)?;
-        writeln!(f, "Provides: {:?}", self.provides)?;
-        writeln!(f, "Depends: {:?}", self.depends)?;
-        writeln!(f, "Make Depends: {:?}", self.make_depends)?;
-        writeln!(f, "Optional Depends: {:?}", self.optional_depends)?;
-        writeln!(f, "Check Depends: {:?}", self.check_depends)?;
+        writeln!(f, "Replaces: {:?}", strings(&self.replaces))?;
+        writeln!(f, "Conflicts: {:?}", strings(&self.conflicts))?;
+        writeln!(f, "Provides: {:?}", strings(&self.provides))?;
+        writeln!(f, "Depends: {:?}", strings(&self.depends))?;
+        writeln!(f, "Make Depends: {:?}", strings(&self.make_depends))?;
+        writeln!(f, "Optional Depends: {:?}", strings(&self.optional_depends))?;
+        writeln!(f, "Check Depends: {:?}", strings(&self.check_depends))?;
         Ok(())
     }
 }
+
+/// Convert the values to strings for display e.g. the dependencies of a package
+pub fn strings<T: ToString>(values: &[T]) -> Vec<String> {
+    values.iter().map(|x| x.to_string()).collect()
+}
+
+/// `InstalledPackage` extends a `Package` with the details pacman records in the local
+/// database when a package is installed.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstalledPackage {
+    pub package: Package,
+    #[cfg_attr(feature = "serde", serde(with = "crate::date::number"))]
+    pub install_date: u64, // Install time in seconds since the epoch, zero if unknown
+    pub reason: InstallReason,
+    pub validation: Vec<Validation>,
+    pub xdata: Vec<(String, String)>,
+    pub mtree: Vec<MtreeEntry>,
+}
+
+/// `InstallReason` records why a package was installed
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum InstallReason {
+    #[default]
+    Explicit,
+    Dependency,
+}
+
+/// `Validation` records how a package was validated when installed
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Validation {
+    None,
+    Md5sum,
+    Sha256sum,
+    Signature,
+}
+
+/// `FileEntry` is a single path owned by a package e.g. `usr/bin/ls`
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileEntry {
+    pub path: String,
+}
+impl FileEntry {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Directories are marked with a trailing slash e.g. `usr/bin/`
+    pub fn is_dir(&self) -> bool {
+        self.path.ends_with('/')
+    }
+}
+
+/// `Backup` is a file pacman preserves on upgrade along with its md5sum at install
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Backup {
+    pub path: String,
+    pub md5sum: String,
+}
@@ -1,73 +1,442 @@
+use crate::dependency::{Dependency, OptionalDependency};
 use crate::error::*;
 use crate::package::*;
+use crate::version::Version;
 use nom::{
     character::complete::{alphanumeric1, char, multispace0, multispace1, not_line_ending},
     combinator::not,
     multi::many1,
     sequence::{preceded, terminated},
 };
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::error::Error as StdError;
+
+/// Keys `repo-add` always writes which strict parsing requires
+const REQUIRED: &[&str] = &[
+    "FILENAME",
+    "NAME",
+    "VERSION",
+    "CSIZE",
+    "ISIZE",
+    "SHA256SUM",
+    "ARCH",
+    "BUILDDATE",
+    "PACKAGER",
+];
+
+/// Keys pacman always writes to a local database `desc` which strict parsing requires
+const INSTALLED_REQUIRED: &[&str] = &[
+    "NAME",
+    "VERSION",
+    "ARCH",
+    "BUILDDATE",
+    "INSTALLDATE",
+    "PACKAGER",
+    "SIZE",
+    "VALIDATION",
+];
+
+/// `ParseOptions` controls how strictly a desc is validated while parsing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseOptions {
+    mode: ParseMode,
+}
+
+/// `ParseMode` selects how problems in a desc are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ParseMode {
+    /// Malformed values and input that can't be parsed are errors while missing and
+    /// duplicate keys are accepted as warnings
+    #[default]
+    Standard,
+
+    /// Missing required keys, duplicate keys and malformed values are errors
+    Strict,
+
+    /// Every problem is collected as a warning and parsing continues
+    Lenient,
+}
+
+impl ParseOptions {
+    /// Options for validating e.g. a mirror where any problem is an error
+    pub fn strict() -> Self {
+        Self::default().with_mode(ParseMode::Strict)
+    }
+
+    /// Options for best effort parsing e.g. browsing a third party repository
+    pub fn lenient() -> Self {
+        Self::default().with_mode(ParseMode::Lenient)
+    }
+
+    /// Set the parse mode
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Parse mode in use
+    pub fn mode(&self) -> ParseMode {
+        self.mode
+    }
+
+    /// Parse desc text into a package along with the problems tolerated as warnings.
+    /// Malformed values are left at their defaults when tolerated.
+    pub fn parse(&self, data: &str) -> Result<(Package, Vec<Error>)> {
+        self.parse_entries(&[(String::new(), data)])
+    }
+
+    /// Parse the desc files of a package in order e.g. `acl-2.2.53-3/desc` and the
+    /// `depends` of older databases. Errors name the entry they came from.
+    pub(crate) fn parse_entries(
+        &self,
+        entries: &[(String, &str)],
+    ) -> Result<(Package, Vec<Error>)> {
+        self.parse_tokens(entries, REQUIRED, parse_property)
+    }
+
+    /// Parse a local database `desc` file naming the entry in any error
+    pub(crate) fn parse_installed(
+        &self,
+        entry: &str,
+        data: &str,
+    ) -> Result<(InstalledPackage, Vec<Error>)> {
+        self.parse_tokens(
+            &[(entry.to_owned(), data)],
+            INSTALLED_REQUIRED,
+            parse_installed_property,
+        )
+    }
+
+    /// Parse the tokens of each entry into the value checking for duplicate, missing
+    /// and malformed keys as well as input the tokenizer couldn't consume
+    fn parse_tokens<T: Default>(
+        &self,
+        entries: &[(String, &str)],
+        required: &[&str],
+        property: for<'a> fn(&mut T, &Token<'a>) -> std::result::Result<(), ValueError<'a>>,
+    ) -> Result<(T, Vec<Error>)> {
+        let mut value = T::default();
+        let mut warnings = Vec::new();
+        let mut seen = HashSet::new();
+
+        for (entry, data) in entries {
+            let mut tokens = Tokenizer::from(*data);
+            for x in &mut tokens {
+                let key = x.name.to_uppercase();
+                if !seen.insert(key.to_owned()) {
+                    let err = ValueError::new(x.values[0], Error::PackagePropertyDuplicate(key));
+                    self.report(err.locate(entry, data, &x), false, &mut warnings)?;
+                }
+                if let Err(err) = property(&mut value, &x) {
+                    self.report(err.locate(entry, data, &x), true, &mut warnings)?;
+                }
+            }
+
+            // The tokenizer stops at the first malformed property e.g. a key without
+            // any values, leaving the rest of the desc unparsed. Dropping it silently
+            // would lose the properties that follow so it counts as malformed.
+            let rest = tokens.rest().trim_start();
+            if !rest.is_empty() {
+                let line = rest.lines().next().unwrap_or_default();
+                let (line_no, column) = location(data, rest);
+                let err = Error::PackageParse(Box::new(ParseError {
+                    entry: entry.to_owned(),
+                    line: Some(line_no),
+                    column: Some(column),
+                    key: name(rest).map(|(_, x)| x.to_owned()).unwrap_or_default(),
+                    desc: true,
+                    value: Some(line.to_owned()),
+                    source: Box::new(Error::PackagePropertyInvalid(line.to_owned())),
+                }));
+                self.report(err, true, &mut warnings)?;
+            }
+        }
+
+        for key in required.iter().filter(|x| !seen.contains(**x)) {
+            let entry = entries.first().map(|x| x.0.to_owned()).unwrap_or_default();
+            let err = Error::PackageParse(Box::new(ParseError {
+                entry,
+                line: None,
+                column: None,
+                key: key.to_string(),
+                desc: true,
+                value: None,
+                source: Box::new(Error::PackagePropertyMissing(key.to_string())),
+            }));
+            self.report(err, false, &mut warnings)?;
+        }
+
+        Ok((value, warnings))
+    }
+
+    /// Fail or collect the problem depending on the mode. Standard mode only fails on
+    /// malformed input and collects the rest.
+    fn report(&self, err: Error, malformed: bool, warnings: &mut Vec<Error>) -> Result<()> {
+        match self.mode {
+            ParseMode::Lenient => warnings.push(err),
+            ParseMode::Strict => return Err(err),
+            ParseMode::Standard if malformed => return Err(err),
+            ParseMode::Standard => warnings.push(err),
+        }
+        Ok(())
+    }
+}
 
 impl TryFrom<&str> for Package {
     type Error = Error;
 
+    /// Parse desc text using the default `ParseOptions`
     fn try_from(data: &str) -> Result<Self> {
-        let mut pkg = Package::default();
-
-        for x in Tokenizer::from(data) {
-            match x.name.to_lowercase().as_str() {
-                "name" if x.values.first().is_some() => pkg.name = x.values[0].to_owned(),
-                "base" if x.values.first().is_some() => pkg.base = x.values[0].to_owned(),
-                "filename" if x.values.first().is_some() => pkg.filename = x.values[0].to_owned(),
-                "version" if x.values.first().is_some() => pkg.version = x.values[0].to_owned(),
-                "desc" if x.values.first().is_some() => pkg.desc = x.values[0].to_owned(),
-                "url" if x.values.first().is_some() => pkg.url = x.values[0].to_owned(),
-                "csize" if x.values.first().is_some() => {
-                    pkg.size = x.values[0]
-                        .parse::<u64>()
-                        .map_err(|_| Error::PackageParseSize)?
-                }
-                "isize" if x.values.first().is_some() => {
-                    pkg.isize = x.values[0]
-                        .parse::<u64>()
-                        .map_err(|_| Error::PackageParseSize)?
-                }
-                "arch" if x.values.first().is_some() => pkg.arch = x.values[0].to_owned(),
-                "md5sum" if x.values.first().is_some() => pkg.md5sum = x.values[0].to_owned(),
-                "sha256sum" if x.values.first().is_some() => pkg.sha256sum = x.values[0].to_owned(),
-                "pgpsig" if x.values.first().is_some() => pkg.pgpsig = x.values[0].to_owned(),
-                "builddate" if x.values.first().is_some() => {
-                    pkg.build_date = x.values[0].to_owned()
-                }
-                "packager" if x.values.first().is_some() => pkg.packager = x.values[0].to_owned(),
-                "license" if x.values.first().is_some() => {
-                    pkg.licenses = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                "provides" if x.values.first().is_some() => {
-                    pkg.provides = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                "depends" if x.values.first().is_some() => {
-                    pkg.depends = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                "makedepends" if x.values.first().is_some() => {
-                    pkg.make_depends = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                "optionaldepends" if x.values.first().is_some() => {
-                    pkg.optional_depends = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                "checkdepends" if x.values.first().is_some() => {
-                    pkg.check_depends = x.values.iter().map(|x| x.to_string()).collect()
-                }
-                _ => return Err(Error::PackagePropertyMissing(x.name.to_string())),
+        Ok(ParseOptions::default().parse(data)?.0)
+    }
+}
+
+impl TryFrom<&str> for InstalledPackage {
+    type Error = Error;
+
+    /// Parse a local database `desc` file including the install only properties
+    fn try_from(data: &str) -> Result<Self> {
+        Ok(ParseOptions::default().parse_installed("", data)?.0)
+    }
+}
+
+/// Parse a property of a local database `desc` file including the install only ones
+fn parse_installed_property<'a>(
+    pkg: &mut InstalledPackage,
+    x: &Token<'a>,
+) -> std::result::Result<(), ValueError<'a>> {
+    match x.name.to_lowercase().as_str() {
+        "installdate" if !x.values.is_empty() => {
+            parse_u64(x.values[0]).map(|v| pkg.install_date = v)
+        }
+        "size" if !x.values.is_empty() => parse_u64(x.values[0]).map(|v| pkg.package.isize = v),
+        "reason" if !x.values.is_empty() => InstallReason::try_from(x.values[0])
+            .map(|v| pkg.reason = v)
+            .map_err(|e| ValueError::new(x.values[0], e)),
+        "validation" if !x.values.is_empty() => x
+            .values
+            .iter()
+            .map(|x| Validation::try_from(*x).map_err(|e| ValueError::new(x, e)))
+            .collect::<std::result::Result<_, _>>()
+            .map(|v| pkg.validation = v),
+        "xdata" if !x.values.is_empty() => x
+            .values
+            .iter()
+            .map(|x| match x.split_once('=') {
+                Some((key, value)) => Ok((key.to_owned(), value.to_owned())),
+                None => Err(ValueError::new(
+                    x,
+                    Error::PackagePropertyInvalid(x.to_string()),
+                )),
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map(|v| pkg.xdata = v),
+        _ => parse_property(&mut pkg.package, x),
+    }
+}
+
+impl TryFrom<&str> for InstallReason {
+    type Error = Error;
+
+    fn try_from(data: &str) -> Result<Self> {
+        match data {
+            "0" => Ok(InstallReason::Explicit),
+            "1" => Ok(InstallReason::Dependency),
+            _ => Err(Error::PackagePropertyInvalid(data.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<&str> for Validation {
+    type Error = Error;
+
+    fn try_from(data: &str) -> Result<Self> {
+        match data {
+            "none" => Ok(Validation::None),
+            "md5" => Ok(Validation::Md5sum),
+            "sha256" => Ok(Validation::Sha256sum),
+            "pgp" => Ok(Validation::Signature),
+            _ => Err(Error::PackagePropertyInvalid(data.to_owned())),
+        }
+    }
+}
+
+/// Parse a `files` entry into the package file list and backup files
+pub(crate) fn parse_files(data: &str) -> Result<(Vec<FileEntry>, Vec<Backup>)> {
+    let mut files = Vec::new();
+    let mut backup = Vec::new();
+
+    for x in Tokenizer::from(data) {
+        match x.name.to_lowercase().as_str() {
+            "files" => files = x.values.iter().map(|x| FileEntry::new(*x)).collect(),
+            "backup" => {
+                backup = x
+                    .values
+                    .iter()
+                    .map(|x| match x.split_once('\t') {
+                        Some((path, md5sum)) => Backup {
+                            path: path.to_owned(),
+                            md5sum: md5sum.to_owned(),
+                        },
+                        None => Backup {
+                            path: x.to_string(),
+                            md5sum: Default::default(),
+                        },
+                    })
+                    .collect()
+            }
+            _ => return Err(Error::PackagePropertyMissing(x.name.to_string())),
+        }
+    }
+
+    Ok((files, backup))
+}
+
+/// Split the `key = value` lines of a `.PKGINFO`, `.BUILDINFO` or `.SRCINFO` into single
+/// value tokens skipping blank lines and comments. Values may themselves contain `=`.
+pub(crate) fn assignments<'a>(
+    entry: &'a str,
+    data: &'a str,
+) -> impl Iterator<Item = Result<Token<'a>>> {
+    data.lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(move |line| match line.split_once('=') {
+            Some((key, value)) => Ok(Token {
+                name: key.trim(),
+                values: vec![value.trim()],
+            }),
+            None => {
+                let err = ValueError::new(line, Error::PackagePropertyInvalid(line.to_owned()));
+                Err(err.locate_key(entry, data, "", false))
             }
+        })
+}
+
+/// Append the value of a repeatable unknown key to `extra` keeping the first position
+pub(crate) fn push_extra(extra: &mut Vec<(String, Vec<String>)>, x: &Token) {
+    let values = x.values.iter().map(|x| x.to_string());
+    match extra.iter_mut().find(|(key, _)| key == x.name) {
+        Some((_, existing)) => existing.extend(values),
+        None => extra.push((x.name.to_owned(), values.collect())),
+    }
+}
+
+/// Parse a single desc property into the given package keeping unknown keys in `extra`
+fn parse_property<'a>(pkg: &mut Package, x: &Token<'a>) -> std::result::Result<(), ValueError<'a>> {
+    match x.name.to_lowercase().as_str() {
+        "name" if !x.values.is_empty() => pkg.name = x.values[0].to_owned(),
+        "base" if !x.values.is_empty() => pkg.base = x.values[0].to_owned(),
+        "filename" if !x.values.is_empty() => pkg.filename = x.values[0].to_owned(),
+        "version" if !x.values.is_empty() => pkg.version = Version::from(x.values[0]),
+        "desc" if !x.values.is_empty() => pkg.desc = x.values[0].to_owned(),
+        "groups" if !x.values.is_empty() => {
+            pkg.groups = x.values.iter().map(|x| x.to_string()).collect()
+        }
+        "url" if !x.values.is_empty() => pkg.url = x.values[0].to_owned(),
+        "csize" if !x.values.is_empty() => pkg.size = parse_u64(x.values[0])?,
+        "isize" if !x.values.is_empty() => pkg.isize = parse_u64(x.values[0])?,
+        "arch" if !x.values.is_empty() => pkg.arch = x.values[0].to_owned(),
+        "md5sum" if !x.values.is_empty() => pkg.md5sum = x.values[0].to_owned(),
+        "sha256sum" if !x.values.is_empty() => pkg.sha256sum = x.values[0].to_owned(),
+        "pgpsig" if !x.values.is_empty() => pkg.pgpsig = x.values[0].to_owned(),
+        "builddate" if !x.values.is_empty() => pkg.build_date = x.values[0].to_owned(),
+        "packager" if !x.values.is_empty() => pkg.packager = x.values[0].to_owned(),
+        "license" if !x.values.is_empty() => {
+            pkg.licenses = x.values.iter().map(|x| x.to_string()).collect()
+        }
+        "provides" if !x.values.is_empty() => {
+            pkg.provides = x.values.iter().map(|x| Dependency::from(*x)).collect()
+        }
+        "replaces" if !x.values.is_empty() => {
+            pkg.replaces = x.values.iter().map(|x| Dependency::from(*x)).collect()
+        }
+        "conflicts" if !x.values.is_empty() => {
+            pkg.conflicts = x.values.iter().map(|x| Dependency::from(*x)).collect()
         }
+        "depends" if !x.values.is_empty() => {
+            pkg.depends = x.values.iter().map(|x| Dependency::from(*x)).collect()
+        }
+        "makedepends" if !x.values.is_empty() => {
+            pkg.make_depends = x.values.iter().map(|x| Dependency::from(*x)).collect()
+        }
+        "optdepends" if !x.values.is_empty() => {
+            pkg.optional_depends = x
+                .values
+                .iter()
+                .map(|x| OptionalDependency::from(*x))
+                .collect()
+        }
+        "checkdepends" if !x.values.is_empty() => {
+            pkg.check_depends = x.values.iter().map(|x| Dependency::from(*x)).collect()
+        }
+        _ => pkg.extra.push((
+            x.name.to_owned(),
+            x.values.iter().map(|x| x.to_string()).collect(),
+        )),
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_u64(value: &str) -> std::result::Result<u64, ValueError<'_>> {
+    value.parse::<u64>().map_err(|e| ValueError::new(value, e))
+}
+
+/// `ValueError` is a problem with a single desc value before its location is known
+pub(crate) struct ValueError<'a> {
+    value: &'a str, // Offending value as a slice of the desc text
+    source: Box<dyn StdError + Send + Sync>,
+}
+impl<'a> ValueError<'a> {
+    pub(crate) fn new(value: &'a str, source: impl Into<Box<dyn StdError + Send + Sync>>) -> Self {
+        Self {
+            value,
+            source: source.into(),
+        }
+    }
 
-        // TODO: Validate required properties were hit
+    /// Convert into an error locating the value within the given desc text
+    fn locate(self, entry: &str, data: &str, token: &Token) -> Error {
+        self.locate_key(entry, data, token.name, true)
+    }
+
+    /// Convert into an error locating the value within the given `key = value` text
+    /// e.g. a `.PKGINFO`
+    pub(crate) fn locate_assignment(self, entry: &str, data: &str, token: &Token) -> Error {
+        self.locate_key(entry, data, token.name, false)
+    }
 
-        Ok(pkg)
+    fn locate_key(self, entry: &str, data: &str, key: &str, desc: bool) -> Error {
+        let (line, column) = location(data, self.value);
+        Error::PackageParse(Box::new(ParseError {
+            entry: entry.to_owned(),
+            line: Some(line),
+            column: Some(column),
+            key: key.to_owned(),
+            desc,
+            value: Some(self.value.to_owned()),
+            source: self.source,
+        }))
     }
 }
 
+/// Line and column of a slice of the given text, both starting at 1
+fn location(data: &str, slice: &str) -> (usize, usize) {
+    let offset = (slice.as_ptr() as usize)
+        .saturating_sub(data.as_ptr() as usize)
+        .min(data.len());
+    let before = &data[..offset];
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[start..].chars().count() + 1,
+    )
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) struct Token<'a> {
     pub(crate) name: &'a str,
@@ -79,6 +448,13 @@ pub(crate) struct Tokenizer<'a> {
     input: &'a str,
 }
 
+impl<'a> Tokenizer<'a> {
+    /// Input not consumed yet e.g. after the tokens stopped at malformed input
+    pub(crate) fn rest(&self) -> &'a str {
+        self.input
+    }
+}
+
 impl<'a> From<&'a str> for Tokenizer<'a> {
     fn from(data: &'a str) -> Self {
         Self { input: data }
@@ -101,7 +477,7 @@ impl<'a> Iterator for Tokenizer<'a> {
 }
 
 /// Parse out a token
-fn token(input: &str) -> nom::IResult<&str, Token> {
+fn token(input: &str) -> nom::IResult<&str, Token<'_>> {
     let (input, name) = name(input)?;
     let (input, values) = many1(values)(input)?;
     Ok((input, Token { name, values }))
@@ -168,7 +544,7 @@ mod tests {
         assert_eq!(
             Package::try_from("%VERSION%\n0.6.2-3\n").unwrap(),
             Package {
-                version: "0.6.2-3".to_string(),
+                version: Version::from("0.6.2-3"),
                 ..Default::default()
             }
         );
@@ -179,6 +555,13 @@ mod tests {
                 ..Default::default()
             }
         );
+        assert_eq!(
+            Package::try_from("%GROUPS%\nbase-devel\n").unwrap(),
+            Package {
+                groups: vec!["base-devel".to_string()],
+                ..Default::default()
+            }
+        );
         assert_eq!(
             Package::try_from("%CSIZE%\n157518488\n").unwrap(),
             Package {
@@ -253,9 +636,27 @@ mod tests {
             Package::try_from("%DEPENDS%\ncurl\nopenal\nlibvorbis\n\n").unwrap(),
             Package {
                 depends: vec![
-                    "curl".to_string(),
-                    "openal".to_string(),
-                    "libvorbis".to_string()
+                    Dependency::from("curl"),
+                    Dependency::from("openal"),
+                    Dependency::from("libvorbis")
+                ],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Package::try_from("%REPLACES%\nxfsacl\n\n%CONFLICTS%\nxfsacl<2\n").unwrap(),
+            Package {
+                replaces: vec![Dependency::from("xfsacl")],
+                conflicts: vec![Dependency::from("xfsacl<2")],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Package::try_from("%OPTDEPENDS%\nbash-completion: for tab completion\nperl\n").unwrap(),
+            Package {
+                optional_depends: vec![
+                    OptionalDependency::from("bash-completion: for tab completion"),
+                    OptionalDependency::from("perl")
                 ],
                 ..Default::default()
             }
@@ -263,12 +664,167 @@ mod tests {
         assert_eq!(
             Package::try_from("%MAKEDEPENDS%\ncmake\nboost\n\n").unwrap(),
             Package {
-                make_depends: vec!["cmake".to_string(), "boost".to_string(),],
+                make_depends: vec![Dependency::from("cmake"), Dependency::from("boost")],
                 ..Default::default()
             }
         );
     }
 
+    use std::num::ParseIntError;
+
+    fn parse_error(err: &Error) -> &ParseError {
+        match *err {
+            Error::PackageParse(ref x) => x,
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_location() {
+        let data = "%NAME%\nfoo\n\n%DESC%\n  héllo wörld\n";
+        let value = &data[data.find("wörld").unwrap()..];
+        assert_eq!(location(data, data), (1, 1));
+        assert_eq!(location(data, value), (5, 9));
+    }
+
+    #[test]
+    fn test_installed_errors() {
+        let err = ParseOptions::default()
+            .parse_installed("yay-10.1.2-1/desc", "%NAME%\nyay\n\n%REASON%\n2\n")
+            .unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!(x.entry, "yay-10.1.2-1/desc");
+        assert_eq!((x.key.as_str(), x.line), ("REASON", Some(5)));
+        assert!(matches!(
+            x.source.downcast_ref::<Error>(),
+            Some(Error::PackagePropertyInvalid(x)) if x == "2"
+        ));
+
+        let err = ParseOptions::default()
+            .parse_installed("yay", "%INSTALLDATE%\nyesterday\n")
+            .unwrap_err();
+        assert_eq!(parse_error(&err).key, "INSTALLDATE");
+    }
+
+    const DESC: &str = "%FILENAME%\ngmp-6.2.1-1-x86_64.pkg.tar.zst\n\n%NAME%\ngmp\n\n\
+        %VERSION%\n6.2.1-1\n\n%CSIZE%\n444516\n\n%ISIZE%\n1124549\n\n\
+        %SHA256SUM%\n6c3c6f6a\n\n%ARCH%\nx86_64\n\n%BUILDDATE%\n1605207003\n\n\
+        %PACKAGER%\nAntonio Rojas <arojas@archlinux.org>\n";
+
+    #[test]
+    fn test_parse_strict() {
+        let (pkg, warnings) = ParseOptions::strict().parse(DESC).unwrap();
+        assert_eq!(pkg.name, "gmp");
+        assert!(warnings.is_empty());
+
+        let data = DESC.replace("%ARCH%\nx86_64\n", "");
+        let err = ParseOptions::strict().parse(&data).unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!(
+            (x.key.as_str(), x.line, x.value.as_ref()),
+            ("ARCH", None, None)
+        );
+        assert!(matches!(
+            x.source.downcast_ref::<Error>(),
+            Some(Error::PackagePropertyMissing(x)) if x == "ARCH"
+        ));
+
+        let data = format!("{}\n%NAME%\ngmp2\n", DESC);
+        let err = ParseOptions::strict().parse(&data).unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!((x.line, x.column), (Some(29), Some(1)));
+        assert!(matches!(
+            x.source.downcast_ref::<Error>(),
+            Some(Error::PackagePropertyDuplicate(x)) if x == "NAME"
+        ));
+
+        let data = DESC.replace("444516", "44x");
+        let err = ParseOptions::strict().parse(&data).unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!(x.key, "CSIZE");
+        assert_eq!(x.value, Some("44x".to_owned()));
+        assert_eq!((x.line, x.column), (Some(11), Some(1)));
+        assert!(x.source.downcast_ref::<ParseIntError>().is_some());
+        assert!(err.source().is_some());
+        assert_eq!(
+            err.to_string(),
+            "package parse failure: 11:1: %CSIZE% \"44x\": invalid digit found in string"
+        );
+
+        // Input the tokenizer stops at e.g. a key without values is rejected
+        let data = format!("{}\n%URL%\n", DESC);
+        let err = ParseOptions::strict().parse(&data).unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!(
+            (x.key.as_str(), x.line, x.column),
+            ("URL", Some(28), Some(1))
+        );
+        let err = ParseOptions::strict()
+            .parse(&format!("junk\n{}", DESC))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "package parse failure: 1:1: \"junk\": package property invalid: junk"
+        );
+        let (_, warnings) = ParseOptions::lenient().parse(&data).unwrap();
+        assert_eq!(warnings.len(), 1);
+
+        // Standard mode rejects malformed input and returns the other problems
+        assert!(Package::try_from(data.as_str()).is_err());
+        assert!(Package::try_from(DESC.replace("444516", "44x").as_str()).is_err());
+        let data = DESC.replace("%ARCH%\nx86_64\n", "");
+        let (_, warnings) = ParseOptions::default().parse(&data).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(parse_error(&warnings[0]).key, "ARCH");
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        // An empty key stops the tokenizer so the properties after it would be lost
+        let data = "%NAME%\nfoo\n\n%DESC%\n\n%VERSION%\n1.0-1\n\n%ARCH%\nx86_64\n";
+        let err = Package::try_from(data).unwrap_err();
+        let x = parse_error(&err);
+        assert_eq!(
+            (x.key.as_str(), x.line, x.column),
+            ("DESC", Some(4), Some(1))
+        );
+
+        let (pkg, warnings) = ParseOptions::lenient().parse(data).unwrap();
+        assert_eq!(pkg.name, "foo");
+        assert_eq!(pkg.version, Version::default());
+        assert_eq!(parse_error(&warnings[0]).key, "DESC");
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let data = format!("{}\n%NAME%\ngmp2\n", DESC)
+            .replace("444516", "44x")
+            .replace("%ARCH%\nx86_64\n", "");
+        let (pkg, warnings) = ParseOptions::lenient().parse(&data).unwrap();
+        assert_eq!(pkg.name, "gmp2");
+        assert_eq!(pkg.size, 0);
+        assert_eq!(pkg.isize, 1124549);
+        assert_eq!(warnings.len(), 3);
+        let keys = warnings
+            .iter()
+            .map(|x| parse_error(x).key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["CSIZE", "NAME", "ARCH"]);
+    }
+
+    #[test]
+    fn test_parse_extra() {
+        let pkg = Package::try_from("%NAME%\nfoo\n\n%ZEBRA%\nb\n\n%APPLE%\na\nc\n").unwrap();
+        assert_eq!(pkg.name, "foo");
+        assert_eq!(
+            pkg.extra,
+            vec![
+                ("ZEBRA".to_owned(), vec!["b".to_owned()]),
+                ("APPLE".to_owned(), vec!["a".to_owned(), "c".to_owned()])
+            ]
+        );
+    }
+
     #[test]
     fn test_multiple_tokens() {
         let (i, x) = token("%name1%\nval1\n\n%name2%\nval2\n\n").unwrap();
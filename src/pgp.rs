@@ -0,0 +1,945 @@
+use crate::prelude::*;
+use crate::signature::{base64, dearmor, hex, packets, parse_error, Bytes, SIGNATURE};
+use ed25519_dalek::Verifier as _;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use sha1::Sha1;
+use sha2::digest::DynDigest;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Packet tags found in keyrings besides signatures
+const PUBLIC_KEY: u8 = 6;
+const USER_ID: u8 = 13;
+const PUBLIC_SUBKEY: u8 = 14;
+
+// Signature types of keyrings besides certifications
+const SUBKEY_BINDING: u8 = 0x18;
+const KEY_REVOCATION: u8 = 0x20;
+const SUBKEY_REVOCATION: u8 = 0x28;
+const CERTIFICATION_REVOCATION: u8 = 0x30;
+
+// Ownertrust levels of `*-trusted` files and how many certifications of each make a
+// key trusted, the defaults of GnuPG as used by pacman
+const MARGINAL: u8 = 4;
+const FULL: u8 = 5;
+const MARGINALS_NEEDED: usize = 3;
+
+/// Object identifier of the Ed25519 curve in an EdDSA key
+const ED25519_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01];
+
+/// `SigLevel` is a signature policy just like the `SigLevel` option of `pacman.conf`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SigLevel {
+    pub check: SigCheck, // Whether a signature is checked and required
+    pub trust: SigTrust, // Which keys are accepted
+}
+
+/// `SigCheck` controls whether signatures are checked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SigCheck {
+    /// Signatures are never checked
+    Never,
+
+    /// Signatures are checked when present and unsigned files are accepted
+    Optional,
+
+    /// Signatures are checked and unsigned files are rejected
+    #[default]
+    Required,
+}
+
+/// `SigTrust` controls which keys a valid signature is accepted from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SigTrust {
+    /// Only keys that are trusted or certified by enough trusted keys
+    #[default]
+    TrustedOnly,
+
+    /// Any key in the keyring that isn't revoked
+    TrustAll,
+}
+
+impl SigLevel {
+    /// Set whether signatures are checked and required
+    pub fn with_check(mut self, check: SigCheck) -> Self {
+        self.check = check;
+        self
+    }
+
+    /// Set which keys are accepted
+    pub fn with_trust(mut self, trust: SigTrust) -> Self {
+        self.trust = trust;
+        self
+    }
+
+    /// Policy for packages from `pacman.conf` values e.g. `Required DatabaseOptional`
+    pub fn package(values: &[String]) -> Result<Self> {
+        Self::parse(values, "Package")
+    }
+
+    /// Policy for databases from `pacman.conf` values e.g. `Required DatabaseOptional`
+    pub fn database(values: &[String]) -> Result<Self> {
+        Self::parse(values, "Database")
+    }
+
+    /// Apply the values in order skipping those prefixed for the other kind of file
+    fn parse(values: &[String], prefix: &str) -> Result<Self> {
+        let mut level = Self::default();
+        for x in values {
+            let value = match x
+                .strip_prefix("Package")
+                .or_else(|| x.strip_prefix("Database"))
+            {
+                Some(_) if !x.starts_with(prefix) => continue,
+                Some(value) => value,
+                None => x.as_str(),
+            };
+            match value {
+                "Never" => level.check = SigCheck::Never,
+                "Optional" => level.check = SigCheck::Optional,
+                "Required" => level.check = SigCheck::Required,
+                "TrustedOnly" => level.trust = SigTrust::TrustedOnly,
+                "TrustAll" => level.trust = SigTrust::TrustAll,
+                _ => return Err(Error::ConfigParse(x.to_owned())),
+            }
+        }
+        Ok(level)
+    }
+}
+
+/// `SignatureStatus` is the outcome of checking a file against a `SigLevel`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SignatureStatus {
+    /// A valid signature by the key with the given fingerprint
+    Valid(String),
+
+    /// No signature was found and the policy allows it
+    Unsigned,
+
+    /// The policy never checks signatures
+    Skipped,
+}
+
+/// `PublicKey` is an OpenPGP version 4 primary key or subkey from a keyring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub fingerprint: String,                 // Hex fingerprint of this key
+    pub primary: String,                     // Fingerprint of the primary key
+    pub user_ids: Vec<String>,               // User IDs e.g. `Jane Doe <jane@example.com>`
+    pub created: u32,                        // Creation time since the epoch
+    pub expires: Option<u32>,                // Expiry time since the epoch if any
+    pub trusted: bool,                       // Trusted or certified by enough trusted keys
+    pub revoked: bool,                       // Listed or signed as revoked
+    material: KeyMaterial,                   // Key of a supported algorithm
+    packet: Vec<u8>,                         // Key packet hashed by certifications
+    certifications: Vec<(usize, Signature)>, // User ID index and certification
+    signatures: Vec<Signature>,              // Key revocations or subkey bindings
+}
+impl PublicKey {
+    /// Key ID as hex e.g. `95B26B15625F4991`
+    pub fn key_id(&self) -> &str {
+        &self.fingerprint[self.fingerprint.len() - 16..]
+    }
+
+    /// Check if the key expired before the given time since the epoch
+    fn expired(&self, now: u32) -> bool {
+        self.expires.is_some_and(|x| x <= now)
+    }
+
+    /// Decode the body of a version 4 public key packet, `None` for other versions
+    fn parse(body: &[u8]) -> Result<Option<Self>> {
+        let mut x = Bytes(body);
+        if x.u8()? != 4 {
+            return Ok(None);
+        }
+        let created = x.u32()?;
+        let material = match PublicKeyAlgorithm::from(x.u8()?) {
+            PublicKeyAlgorithm::Rsa => {
+                let n = BigUint::from_bytes_be(x.mpi()?);
+                let e = BigUint::from_bytes_be(x.mpi()?);
+                RsaPublicKey::new(n, e)
+                    .map(KeyMaterial::Rsa)
+                    .map_err(|e| parse_error(&e.to_string()))?
+            }
+            PublicKeyAlgorithm::EdDsa => {
+                let len = x.u8()? as usize;
+                let oid = x.take(len)?;
+                match x.mpi()? {
+                    [0x40, point @ ..] if oid == ED25519_OID && point.len() == 32 => {
+                        let mut bytes = [0; 32];
+                        bytes.copy_from_slice(point);
+                        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+                            .map(KeyMaterial::Ed25519)
+                            .map_err(|e| parse_error(&e.to_string()))?
+                    }
+                    _ => KeyMaterial::Unsupported,
+                }
+            }
+            _ => KeyMaterial::Unsupported,
+        };
+
+        let mut data = vec![0x99];
+        data.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        data.extend_from_slice(body);
+        let fingerprint = hex(&Sha1::digest(&data));
+
+        Ok(Some(Self {
+            primary: fingerprint.to_owned(),
+            fingerprint,
+            user_ids: Vec::new(),
+            created,
+            expires: None,
+            trusted: false,
+            revoked: false,
+            material,
+            packet: body.to_vec(),
+            certifications: Vec::new(),
+            signatures: Vec::new(),
+        }))
+    }
+
+    /// Check the signature against the hash of the signed data
+    fn verify_digest(&self, sig: &Signature, digest: &[u8]) -> bool {
+        if !digest.starts_with(&sig.digest_prefix) {
+            return false;
+        }
+        match (&self.material, sig.values.as_slice()) {
+            (KeyMaterial::Rsa(key), [value]) => {
+                let scheme = match sig.hash_algorithm {
+                    HashAlgorithm::Sha1 => Pkcs1v15Sign::new::<Sha1>(),
+                    HashAlgorithm::Sha224 => Pkcs1v15Sign::new::<Sha224>(),
+                    HashAlgorithm::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+                    HashAlgorithm::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+                    HashAlgorithm::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+                    _ => return false,
+                };
+                let size = rsa::traits::PublicKeyParts::size(key);
+                key.verify(scheme, digest, &pad(value, size)).is_ok()
+            }
+            (KeyMaterial::Ed25519(key), [r, s]) => {
+                let mut bytes = pad(r, 32);
+                bytes.extend(pad(s, 32));
+                match ed25519_dalek::Signature::from_slice(&bytes) {
+                    Ok(x) => key.verify(digest, &x).is_ok(),
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Key material of the supported algorithms
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeyMaterial {
+    Rsa(RsaPublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Unsupported,
+}
+
+/// `Keyring` holds public keys along with which are trusted or revoked, loaded from a
+/// directory laid out like `archlinux-keyring` e.g. `archlinux.gpg`,
+/// `archlinux-trusted` and `archlinux-revoked`.
+#[derive(Debug, Default, Clone)]
+pub struct Keyring {
+    keys: Vec<PublicKey>,
+}
+impl Keyring {
+    /// Load the keyring files in the given directory. Keys are read from `.gpg`,
+    /// `.asc`, `.pgp` and `.key` files either binary or ASCII armored. Keys listed in
+    /// `*-trusted` files are trusted with their ownertrust e.g. `FPR:4:` for marginal,
+    /// the default, and `FPR:5:` for full. Like GnuPG for pacman any other key is
+    /// trusted once certified by one fully or three marginally trusted keys. Keys
+    /// listed in `*-revoked` files are revoked.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let load = |e| Error::KeyringLoad(dir.to_owned(), e);
+        let mut paths = fs::read_dir(dir)
+            .map_err(load)?
+            .map(|x| x.map(|x| x.path()))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(load)?;
+        paths.sort();
+
+        let mut keyring = Self::default();
+        let mut trusted = HashMap::new();
+        let mut revoked = HashSet::new();
+        for path in paths.iter().filter(|x| x.is_file()) {
+            let load = |e| Error::KeyringLoad(path.to_owned(), e);
+            let name = path.to_string_lossy();
+            if name.ends_with("-trusted") || name.ends_with("-revoked") {
+                let data = fs::read_to_string(path).map_err(load)?;
+                for line in data.lines() {
+                    let mut fields = line.split(':');
+                    let fingerprint = fields.next().unwrap_or_default().trim().to_uppercase();
+                    if fingerprint.is_empty() {
+                        continue;
+                    }
+                    if name.ends_with("-revoked") {
+                        revoked.insert(fingerprint);
+                    } else {
+                        let level = fields.next().and_then(|x| x.trim().parse().ok());
+                        trusted.insert(fingerprint, level.unwrap_or(MARGINAL));
+                    }
+                }
+            } else if let Some("gpg" | "asc" | "pgp" | "key") =
+                path.extension().and_then(|x| x.to_str())
+            {
+                keyring.insert(&fs::read(path).map_err(load)?)?;
+            }
+        }
+        keyring.validate(&trusted, &revoked, now());
+
+        Ok(keyring)
+    }
+
+    /// Keys in the order loaded
+    pub fn keys(&self) -> &[PublicKey] {
+        &self.keys
+    }
+
+    /// Get a key by fingerprint or key ID
+    pub fn get(&self, id: &str) -> Option<&PublicKey> {
+        let id = id.to_uppercase();
+        self.keys.iter().find(|x| x.fingerprint.ends_with(&id))
+    }
+
+    /// Verify a detached signature over the given file returning the signing key.
+    /// Revoked or expired keys and expired signatures are always rejected, untrusted
+    /// keys unless trusting all.
+    pub fn verify(
+        &self,
+        path: impl AsRef<Path>,
+        signature: &[u8],
+        trust: SigTrust,
+    ) -> Result<&PublicKey> {
+        let path = path.as_ref();
+        let sig = Signature::from_bytes(signature)?;
+        if sig.kind != 0 {
+            return Err(parse_error(&format!(
+                "unsupported signature type {}",
+                sig.kind
+            )));
+        }
+        let key = self.issuer(&sig).ok_or_else(|| {
+            Error::SignatureKeyUnknown(sig.key_id().unwrap_or_default().to_owned())
+        })?;
+        let now = now();
+        if key.revoked {
+            return Err(Error::SignatureKeyRevoked(key.fingerprint.to_owned()));
+        }
+        if key.expired(now) {
+            return Err(Error::SignatureKeyExpired(key.fingerprint.to_owned()));
+        }
+
+        let mut hasher = hasher(sig.hash_algorithm)?;
+        let load = |e| Error::SignatureLoad(path.to_owned(), e);
+        let mut f = File::open(path).map_err(load)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match f.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(load(e)),
+            }
+        }
+        hasher.update(&sig.trailer());
+        if !key.verify_digest(&sig, &hasher.finalize()) {
+            return Err(Error::SignatureInvalid(path.display().to_string()));
+        }
+        if expired(&sig, now) {
+            return Err(Error::SignatureExpired(path.display().to_string()));
+        }
+
+        if trust == SigTrust::TrustedOnly && !key.trusted {
+            return Err(Error::SignatureKeyUntrusted(key.fingerprint.to_owned()));
+        }
+        Ok(key)
+    }
+
+    /// Check a package file against the policy using the package `%PGPSIG%` if given
+    /// otherwise a detached `.sig` file next to it, just as pacman does
+    pub fn verify_package(
+        &self,
+        pkg: &Package,
+        path: impl AsRef<Path>,
+        level: SigLevel,
+    ) -> Result<SignatureStatus> {
+        let path = path.as_ref();
+        let signature = match pkg.pgpsig.as_str() {
+            "" => detached(path)?,
+            x => Some(base64(x)?),
+        };
+        self.check(path, signature, level)
+    }
+
+    /// Check a database file e.g. `core.db` against the policy using its detached
+    /// `core.db.sig` file
+    pub fn verify_database(
+        &self,
+        path: impl AsRef<Path>,
+        level: SigLevel,
+    ) -> Result<SignatureStatus> {
+        let path = path.as_ref();
+        self.check(path, detached(path)?, level)
+    }
+
+    fn check(
+        &self,
+        path: &Path,
+        signature: Option<Vec<u8>>,
+        level: SigLevel,
+    ) -> Result<SignatureStatus> {
+        match (level.check, signature) {
+            (SigCheck::Never, _) => Ok(SignatureStatus::Skipped),
+            (SigCheck::Required, None) => Err(Error::SignatureMissing(path.display().to_string())),
+            (SigCheck::Optional, None) => Ok(SignatureStatus::Unsigned),
+            (_, Some(x)) => {
+                let key = self.verify(path, &x, level.trust)?;
+                Ok(SignatureStatus::Valid(key.fingerprint.to_owned()))
+            }
+        }
+    }
+
+    /// Find the key that issued the signature
+    fn issuer(&self, sig: &Signature) -> Option<&PublicKey> {
+        match sig.issuer_fingerprint {
+            Some(ref x) => self.keys.iter().find(|key| &key.fingerprint == x),
+            None => self.get(sig.key_id()?),
+        }
+    }
+
+    /// Add the keys in binary or ASCII armored OpenPGP data. User IDs and their
+    /// certifications are kept with the primary key, key revocations and subkey
+    /// bindings with the key they follow.
+    fn insert(&mut self, data: &[u8]) -> Result<()> {
+        let data = dearmor(data)?;
+        let mut primary: Option<usize> = None;
+        let mut user_id: Option<usize> = None;
+        for x in packets(&data)? {
+            match x.tag {
+                PUBLIC_KEY | PUBLIC_SUBKEY => {
+                    user_id = None;
+                    let mut key = match PublicKey::parse(x.body)? {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    match primary {
+                        Some(i) if x.tag == PUBLIC_SUBKEY => {
+                            key.primary = self.keys[i].fingerprint.to_owned();
+                            key.user_ids = self.keys[i].user_ids.to_owned();
+                        }
+                        _ => primary = Some(self.keys.len()),
+                    }
+                    self.keys.push(key);
+                }
+                USER_ID => {
+                    if let Some(key) = primary.map(|i| &mut self.keys[i]) {
+                        user_id = Some(key.user_ids.len());
+                        key.user_ids
+                            .push(String::from_utf8_lossy(x.body).into_owned());
+                    }
+                }
+                SIGNATURE => {
+                    let i = match primary {
+                        Some(i) => i,
+                        None => continue,
+                    };
+                    let sig = Signature::parse(x.body)?;
+                    match (user_id, sig.kind) {
+                        (Some(uid), 0x10..=0x13 | CERTIFICATION_REVOCATION) => {
+                            self.keys[i].certifications.push((uid, sig))
+                        }
+                        (None, SUBKEY_BINDING | KEY_REVOCATION | SUBKEY_REVOCATION) => {
+                            self.keys.last_mut().unwrap().signatures.push(sig)
+                        }
+                        _ => {}
+                    }
+                }
+                _ => user_id = None,
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the trusted, revoked and expired keys. Subkeys without a valid binding
+    /// to their primary key are dropped. A key is trusted when its primary key is
+    /// listed or enough listed keys certified one of its user IDs, without having
+    /// revoked that certification since.
+    fn validate(&mut self, trusted: &HashMap<String, u8>, revoked: &HashSet<String>, now: u32) {
+        let primary = |key: &PublicKey| self.keys.iter().find(|x| x.fingerprint == key.primary);
+        let states = self
+            .keys
+            .iter()
+            .map(|key| match primary(key) {
+                Some(x) if x.fingerprint == key.fingerprint => Some(self_state(key)),
+                Some(x) => subkey_state(x, key),
+                None => None,
+            })
+            .collect::<Vec<_>>();
+        self.keys = std::mem::take(&mut self.keys)
+            .into_iter()
+            .zip(states)
+            .filter_map(|(mut key, state)| {
+                let (signed, expires) = state?;
+                key.revoked = signed || revoked.contains(&key.fingerprint);
+                key.expires = expires;
+                Some(key)
+            })
+            .collect();
+
+        // Subkeys share the revocation and expiry of their primary key
+        let primaries = self
+            .keys
+            .iter()
+            .filter(|key| key.fingerprint == key.primary)
+            .map(|key| (key.fingerprint.to_owned(), (key.revoked, key.expires)))
+            .collect::<HashMap<_, _>>();
+        for key in self.keys.iter_mut() {
+            if let Some(&(signed, expires)) = primaries.get(&key.primary) {
+                key.revoked |= signed || revoked.contains(&key.primary);
+                key.expires = key.expires.into_iter().chain(expires).min();
+            }
+        }
+
+        let certified = self
+            .keys
+            .iter()
+            .filter(|key| key.fingerprint == key.primary)
+            .filter(|key| {
+                (0..key.user_ids.len()).any(|uid| {
+                    let levels = self.certifiers(key, uid, trusted, now);
+                    levels.iter().any(|x| *x >= FULL)
+                        || levels.iter().filter(|x| **x == MARGINAL).count() >= MARGINALS_NEEDED
+                })
+            })
+            .map(|key| key.fingerprint.to_owned())
+            .collect::<HashSet<_>>();
+
+        for key in self.keys.iter_mut() {
+            key.trusted = !key.revoked
+                && !key.expired(now)
+                && (trusted.contains_key(&key.primary) || certified.contains(&key.primary));
+        }
+    }
+
+    /// Ownertrust of each valid listed key with a current certification of the user
+    /// ID of the primary key
+    fn certifiers(
+        &self,
+        key: &PublicKey,
+        uid: usize,
+        trusted: &HashMap<String, u8>,
+        now: u32,
+    ) -> Vec<u8> {
+        let user_id = key.user_ids[uid].as_str();
+        let sigs = key
+            .certifications
+            .iter()
+            .filter(|x| x.0 == uid)
+            .filter_map(|(_, sig)| Some((self.issuer(sig)?, sig)))
+            .filter(|(signer, _)| {
+                signer.fingerprint == signer.primary && signer.primary != key.primary
+            })
+            .filter(|(signer, sig)| signs(signer, &[key], Some(user_id), sig))
+            .collect::<Vec<_>>();
+
+        let mut levels = HashMap::new();
+        for (signer, sig) in sigs.iter() {
+            let level = match trusted.get(&signer.fingerprint) {
+                Some(x) if !signer.revoked && !signer.expired(now) => *x,
+                _ => continue,
+            };
+            let revoked = sigs.iter().any(|(x, revocation)| {
+                revocation.kind == CERTIFICATION_REVOCATION
+                    && x.fingerprint == signer.fingerprint
+                    && revocation.created >= sig.created
+            });
+            if sig.kind != CERTIFICATION_REVOCATION && !revoked && !expired(sig, now) {
+                levels.insert(signer.fingerprint.as_str(), level);
+            }
+        }
+        levels.into_values().collect()
+    }
+}
+
+/// Revocation and expiry of a primary key from its own signatures. The expiry is
+/// taken from the latest self-certification.
+fn self_state(key: &PublicKey) -> (bool, Option<u32>) {
+    let revoked = key
+        .signatures
+        .iter()
+        .filter(|sig| sig.kind == KEY_REVOCATION)
+        .any(|sig| signs(key, &[key], None, sig));
+    let expires = key
+        .certifications
+        .iter()
+        .filter(|(_, sig)| (0x10..=0x13).contains(&sig.kind))
+        .filter(|(uid, sig)| signs(key, &[key], Some(&key.user_ids[*uid]), sig))
+        .max_by_key(|(_, sig)| sig.created)
+        .and_then(|(_, sig)| sig.key_expires);
+    (revoked, expires.map(|x| key.created.saturating_add(x)))
+}
+
+/// Revocation and expiry of a subkey from the signatures of its primary key, `None`
+/// when the primary key never bound it. The expiry is taken from the latest binding.
+fn subkey_state(primary: &PublicKey, key: &PublicKey) -> Option<(bool, Option<u32>)> {
+    let signed = |kind| {
+        key.signatures
+            .iter()
+            .filter(move |sig| sig.kind == kind)
+            .filter(|sig| signs(primary, &[primary, key], None, sig))
+    };
+    let binding = signed(SUBKEY_BINDING).max_by_key(|sig| sig.created)?;
+    let revoked = signed(SUBKEY_REVOCATION).next().is_some();
+    let expires = binding.key_expires.map(|x| key.created.saturating_add(x));
+    Some((revoked, expires))
+}
+
+/// Check the signature by the signer over the given key packets followed by the user
+/// ID if any e.g. a certification, a subkey binding or a revocation
+fn signs(signer: &PublicKey, keys: &[&PublicKey], user_id: Option<&str>, sig: &Signature) -> bool {
+    let mut hasher = match hasher(sig.hash_algorithm) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    for key in keys {
+        hasher.update(&[0x99]);
+        hasher.update(&(key.packet.len() as u16).to_be_bytes());
+        hasher.update(&key.packet);
+    }
+    if let Some(user_id) = user_id {
+        hasher.update(&[0xb4]);
+        hasher.update(&(user_id.len() as u32).to_be_bytes());
+        hasher.update(user_id.as_bytes());
+    }
+    hasher.update(&sig.trailer());
+    signer.verify_digest(sig, &hasher.finalize())
+}
+
+/// Check if the validity period of the signature has passed
+fn expired(sig: &Signature, now: u32) -> bool {
+    match (sig.created, sig.expires) {
+        (Some(created), Some(x)) => created.saturating_add(x) <= now,
+        _ => false,
+    }
+}
+
+/// Current time since the epoch
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() as u32)
+}
+
+/// Hasher for the given signature hash algorithm
+fn hasher(algorithm: HashAlgorithm) -> Result<Box<dyn DynDigest>> {
+    match algorithm {
+        HashAlgorithm::Sha1 => Ok(Box::new(Sha1::default())),
+        HashAlgorithm::Sha224 => Ok(Box::new(Sha224::default())),
+        HashAlgorithm::Sha256 => Ok(Box::new(Sha256::default())),
+        HashAlgorithm::Sha384 => Ok(Box::new(Sha384::default())),
+        HashAlgorithm::Sha512 => Ok(Box::new(Sha512::default())),
+        x => Err(parse_error(&format!("unsupported hash algorithm {}", x))),
+    }
+}
+
+/// Read the detached signature next to the given file e.g. `core.db.sig` if any
+fn detached(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut sig = path.as_os_str().to_owned();
+    sig.push(".sig");
+    let sig = PathBuf::from(sig);
+    match fs::read(&sig) {
+        Ok(data) => Ok(Some(data)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::SignatureLoad(sig, e)),
+    }
+}
+
+/// Left pad a multiprecision integer with zeros to the given length
+fn pad(value: &[u8], len: usize) -> Vec<u8> {
+    let mut padded = vec![0; len.saturating_sub(value.len())];
+    padded.extend_from_slice(value);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::PGPSIG;
+
+    const MASTER: &str = "02B985ECB3617A668307E0CABF5F1391BA279599";
+    const MASTER2: &str = "26C2A594E3711E1DD774DA5ED9C78D6416BD9858";
+    const PACKAGER: &str = "5774B0B8EF47A7AED5598D3E95B26B15625F4991";
+    const SUBKEY: &str = "231C9319FDC90E6EABCC129849FCA23EC1E7CEAF";
+    const ROGUE: &str = "77A3C5D2009A0843DB21584C06520CC7A9101E0B";
+    const ROGUE_SUBKEY: &str = "B0C6B6FE1C640F78ABF6E09AD29E1E81D52A5B72";
+    const EXPIRED: &str = "B4A5F0F0CC145CB71C8CA1F54B2F945D82E218F8";
+    const ZST: &str = "tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst";
+    const XZ: &str = "tests/pkg/foo-1.0-1-x86_64.pkg.tar.xz";
+
+    fn keyring() -> Keyring {
+        Keyring::load("tests/pgp/keyring").unwrap()
+    }
+
+    /// Load the given keys with the given `*-trusted` list
+    fn keyring_with(keys: &str, trusted: &str) -> Keyring {
+        let dir = tempfile::tempdir().unwrap();
+        fs::copy(keys, dir.path().join("test.gpg")).unwrap();
+        fs::write(dir.path().join("test-trusted"), trusted).unwrap();
+        Keyring::load(dir.path()).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        let keyring = keyring();
+        assert_eq!(keyring.keys().len(), 8);
+        let master = keyring.get(MASTER).unwrap();
+        assert!(master.trusted);
+        assert_eq!(
+            master.user_ids,
+            vec!["Test Master Key <master@example.com>"]
+        );
+
+        // Certified by three marginally trusted keys
+        let packager = keyring.get("95b26b15625f4991").unwrap();
+        assert_eq!(packager.fingerprint, PACKAGER);
+        assert_eq!(packager.key_id(), "95B26B15625F4991");
+        assert_eq!(packager.expires, None);
+        assert!(packager.trusted);
+        let subkey = keyring.get(SUBKEY).unwrap();
+        assert_eq!(subkey.primary, PACKAGER);
+        assert!(subkey.trusted);
+
+        // Certified by a single marginally trusted key
+        assert!(!keyring.get(ROGUE).unwrap().trusted);
+
+        let expired = keyring.get(EXPIRED).unwrap();
+        assert_eq!(expired.expires, Some(1640995200));
+        assert!(!expired.trusted);
+    }
+
+    #[test]
+    fn test_ownertrust() {
+        let keys = "tests/pgp/keyring/test.gpg";
+        let keyring = keyring_with(keys, &format!("{}:4:\n{}:4:\n", MASTER, MASTER2));
+        assert!(keyring.get(MASTER2).unwrap().trusted);
+        assert!(!keyring.get(PACKAGER).unwrap().trusted);
+
+        // A fully trusted key is enough on its own
+        let keyring = keyring_with(keys, &format!("{}:5:\n", MASTER));
+        assert!(keyring.get(PACKAGER).unwrap().trusted);
+        assert!(keyring.get(ROGUE).unwrap().trusted);
+        assert!(!keyring.get(MASTER2).unwrap().trusted);
+
+        // Marginal without a level
+        let keyring = keyring_with(keys, &format!("{}\n", MASTER));
+        assert!(keyring.get(MASTER).unwrap().trusted);
+        assert!(!keyring.get(ROGUE).unwrap().trusted);
+    }
+
+    #[test]
+    fn test_verify() {
+        let keyring = keyring();
+        let sig = fs::read(format!("{}.sig", ZST)).unwrap();
+        let key = keyring.verify(ZST, &sig, SigTrust::TrustedOnly).unwrap();
+        assert_eq!(key.fingerprint, PACKAGER);
+
+        // Signed by a key that nobody trusted certified
+        let sig = fs::read(format!("{}.sig", XZ)).unwrap();
+        assert!(matches!(
+            keyring.verify(XZ, &sig, SigTrust::TrustedOnly),
+            Err(Error::SignatureKeyUntrusted(x)) if x == ROGUE
+        ));
+        assert!(keyring.verify(XZ, &sig, SigTrust::TrustAll).is_ok());
+
+        // Signature for a different file
+        assert!(matches!(
+            keyring.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureInvalid(_))
+        ));
+
+        let sig = fs::read("tests/pgp/subkey.sig").unwrap();
+        let key = keyring.verify(ZST, &sig, SigTrust::TrustedOnly).unwrap();
+        assert_eq!(key.fingerprint, SUBKEY);
+    }
+
+    #[test]
+    fn test_verify_expired() {
+        let keyring = keyring();
+        let sig = fs::read("tests/pgp/expired.sig").unwrap();
+        assert!(matches!(
+            keyring.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureKeyExpired(x)) if x == EXPIRED
+        ));
+        let sig = fs::read("tests/pgp/sig-expired.sig").unwrap();
+        assert!(matches!(
+            keyring.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureExpired(_))
+        ));
+    }
+
+    #[test]
+    fn test_unbound_subkey() {
+        // The rogue subkey follows the packager key but is bound by the rogue key
+        let trusted = fs::read_to_string("tests/pgp/keyring/test-trusted").unwrap();
+        let unbound = keyring_with("tests/pgp/unbound.gpg", &trusted);
+        assert!(unbound.get(ROGUE_SUBKEY).is_none());
+        assert!(unbound.get(SUBKEY).unwrap().trusted);
+        let sig = fs::read("tests/pgp/unbound.sig").unwrap();
+        assert!(matches!(
+            unbound.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureKeyUnknown(x)) if x == "D29E1E81D52A5B72"
+        ));
+
+        let keyring = keyring();
+        assert_eq!(keyring.get(ROGUE_SUBKEY).unwrap().primary, ROGUE);
+        assert!(keyring.verify(ZST, &sig, SigTrust::TrustAll).is_ok());
+    }
+
+    #[test]
+    fn test_verify_package() {
+        let keyring = keyring();
+        let mut pkg = Package::default();
+        let status = keyring
+            .verify_package(&pkg, ZST, SigLevel::default())
+            .unwrap();
+        assert_eq!(status, SignatureStatus::Valid(PACKAGER.to_owned()));
+
+        // The `%PGPSIG%` takes priority over the detached signature
+        pkg.pgpsig = PGPSIG.to_owned();
+        assert!(keyring
+            .verify_package(&pkg, ZST, SigLevel::default())
+            .is_ok());
+        assert!(matches!(
+            keyring.verify_package(&pkg, XZ, SigLevel::default()),
+            Err(Error::SignatureInvalid(_))
+        ));
+
+        let level = SigLevel::default().with_check(SigCheck::Never);
+        assert_eq!(
+            keyring.verify_package(&pkg, XZ, level).unwrap(),
+            SignatureStatus::Skipped
+        );
+    }
+
+    #[test]
+    fn test_verify_database() {
+        let keyring = keyring();
+        let status = keyring
+            .verify_database("tests/pgp/signed.db", SigLevel::default())
+            .unwrap();
+        assert_eq!(status, SignatureStatus::Valid(MASTER.to_owned()));
+
+        let path = "tests/compression/none.db";
+        assert!(matches!(
+            keyring.verify_database(path, SigLevel::default()),
+            Err(Error::SignatureMissing(_))
+        ));
+        let level = SigLevel::default().with_check(SigCheck::Optional);
+        assert_eq!(
+            keyring.verify_database(path, level).unwrap(),
+            SignatureStatus::Unsigned
+        );
+
+        // Tampering breaks the signature
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signed.db");
+        let mut data = fs::read("tests/pgp/signed.db").unwrap();
+        data[100] ^= 1;
+        fs::write(&path, data).unwrap();
+        fs::copy("tests/pgp/signed.db.sig", dir.path().join("signed.db.sig")).unwrap();
+        assert!(matches!(
+            keyring.verify_database(&path, SigLevel::default()),
+            Err(Error::SignatureInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_revoked() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in &["test.gpg", "test-trusted"] {
+            fs::copy(
+                Path::new("tests/pgp/keyring").join(name),
+                dir.path().join(name),
+            )
+            .unwrap();
+        }
+        fs::write(dir.path().join("test-revoked"), format!("{}\n", PACKAGER)).unwrap();
+        let keyring = Keyring::load(dir.path()).unwrap();
+        assert!(keyring.get(PACKAGER).unwrap().revoked);
+        assert!(!keyring.get(PACKAGER).unwrap().trusted);
+
+        let sig = fs::read(format!("{}.sig", ZST)).unwrap();
+        assert!(matches!(
+            keyring.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureKeyRevoked(x)) if x == PACKAGER
+        ));
+
+        // Without the trusted list nothing is trusted
+        fs::remove_file(dir.path().join("test-trusted")).unwrap();
+        let keyring = Keyring::load(dir.path()).unwrap();
+        assert!(keyring.keys().iter().all(|x| !x.trusted));
+    }
+
+    #[test]
+    fn test_revocation_signatures() {
+        let trusted = fs::read_to_string("tests/pgp/keyring/test-trusted").unwrap();
+        let keyring = keyring_with("tests/pgp/revoked.gpg", &trusted);
+        assert!(keyring.get(ROGUE).unwrap().revoked);
+        assert!(keyring.get(ROGUE_SUBKEY).unwrap().revoked);
+        assert!(!keyring.get(PACKAGER).unwrap().revoked);
+        let sig = fs::read(format!("{}.sig", XZ)).unwrap();
+        assert!(matches!(
+            keyring.verify(XZ, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureKeyRevoked(x)) if x == ROGUE
+        ));
+
+        // The second master key revoked its certification leaving two
+        let keyring = keyring_with("tests/pgp/certrevoked.gpg", &trusted);
+        assert!(!keyring.get(PACKAGER).unwrap().revoked);
+        assert!(!keyring.get(PACKAGER).unwrap().trusted);
+
+        let keyring = keyring_with("tests/pgp/subkey-revoked.gpg", &trusted);
+        assert!(keyring.get(PACKAGER).unwrap().trusted);
+        assert!(keyring.get(SUBKEY).unwrap().revoked);
+        let sig = fs::read("tests/pgp/subkey.sig").unwrap();
+        assert!(matches!(
+            keyring.verify(ZST, &sig, SigTrust::TrustAll),
+            Err(Error::SignatureKeyRevoked(x)) if x == SUBKEY
+        ));
+    }
+
+    #[test]
+    fn test_sig_level() {
+        let values = |x: &str| {
+            x.split_whitespace()
+                .map(|x| x.to_owned())
+                .collect::<Vec<_>>()
+        };
+        let level = SigLevel::package(&values("Required DatabaseOptional")).unwrap();
+        assert_eq!(level, SigLevel::default());
+        let level = SigLevel::database(&values("Required DatabaseOptional")).unwrap();
+        assert_eq!(level.check, SigCheck::Optional);
+        let level = SigLevel::package(&values("Optional TrustAll")).unwrap();
+        assert_eq!(
+            level,
+            SigLevel::default()
+                .with_check(SigCheck::Optional)
+                .with_trust(SigTrust::TrustAll)
+        );
+        assert!(SigLevel::package(&values("Sometimes")).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::compression::decoder;
+use crate::mtree::{self, MtreeEntry};
+use crate::package::{FileEntry, Package};
+use crate::parser::{assignments, parse_u64, push_extra};
+use crate::prelude::*;
+use crate::tar::TarReader;
+use crate::verify::digests;
+use ::tar::EntryType;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// `PackageFile` is a built package e.g. `foo-1.0-1-x86_64.pkg.tar.zst` read from disk
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackageFile {
+    pub package: Package,              // From `.PKGINFO` plus the archive details
+    pub build_info: Option<BuildInfo>, // From `.BUILDINFO` if any
+    pub mtree: Vec<MtreeEntry>,        // From `.MTREE` if any
+}
+impl PackageFile {
+    /// Open a package file and read its metadata. The package is filled in just as
+    /// `repo-add` would e.g. with the file name, size, checksums and file list.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let load = |e| Error::PackageLoad(path.to_owned(), e);
+        let filename = path
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut pkg = None;
+        let mut build_info = None;
+        let mut mtree = Vec::new();
+        let mut files = Vec::new();
+
+        // Only the metadata is read, the payload files are skipped as they stream past
+        let f = File::open(path).map_err(load)?;
+        for entry in TarReader::new(decoder(f, load)?).with_filter(is_metadata) {
+            let entry = entry.map_err(load)?;
+            match entry.path.trim_start_matches("./") {
+                x @ ".PKGINFO" => pkg = Some(parse_pkginfo(x, &utf8(&filename, x, entry.data)?)?),
+                x @ ".BUILDINFO" => {
+                    build_info = Some(parse_buildinfo(x, &utf8(&filename, x, entry.data)?)?)
+                }
+                ".MTREE" => {
+                    let mut data = String::new();
+                    decoder(&entry.data[..], load)?
+                        .read_to_string(&mut data)
+                        .map_err(load)?;
+                    mtree = mtree::parse(&data)?;
+                }
+                x if x.starts_with('.') => {}
+                x if entry.kind == EntryType::Directory && !x.ends_with('/') => {
+                    files.push(FileEntry::new(format!("{}/", x)))
+                }
+                x => files.push(FileEntry::new(x)),
+            }
+        }
+
+        let mut pkg = pkg.ok_or_else(|| Error::PackageDescMissing(filename.to_owned()))?;
+        let (md5sum, sha256sum) = digests(path).map_err(load)?;
+        files.sort_by(|x, y| x.path.cmp(&y.path));
+        pkg.filename = filename;
+        pkg.size = path.metadata().map_err(load)?.len();
+        pkg.md5sum = md5sum;
+        pkg.sha256sum = sha256sum;
+        pkg.files = files;
+
+        Ok(Self {
+            package: pkg,
+            build_info,
+            mtree,
+        })
+    }
+}
+
+/// `BuildInfo` describes the environment a package was built in from its `.BUILDINFO`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[rustfmt::skip]
+pub struct BuildInfo {
+    pub format: String,                    // Format version e.g. `2`
+    pub name: String,                      // Package name e.g. `foo`
+    pub base: String,                      // Package base e.g. `foo`
+    pub version: Version,                  // Package version e.g. `1.0-1`
+    pub arch: String,                      // Package architecture e.g. `x86_64`
+    pub pkgbuild_sha256sum: String,        // Checksum of the PKGBUILD used
+    pub packager: String,                  // Packager e.g. `Jane Doe <jane@example.com>`
+    #[cfg_attr(feature = "serde", serde(with = "crate::date::text"))]
+    pub build_date: String,                // Build time in seconds since the epoch
+    pub build_dir: String,                 // Directory the package was built in e.g. `/build`
+    pub start_dir: String,                 // Directory makepkg was started from
+    pub build_tool: String,                // Tool used to build e.g. `devtools`
+    pub build_tool_version: String,        // Version of the build tool
+    pub build_env: Vec<String>,            // makepkg `BUILDENV` e.g. `!distcc`
+    pub options: Vec<String>,              // makepkg `OPTIONS` e.g. `strip`
+    pub installed: Vec<String>,            // Packages in the build root e.g. `glibc-2.32-5-x86_64`
+    pub extra: Vec<(String, Vec<String>)>, // Unknown keys in the order given
+}
+
+/// Parse the `.PKGINFO` of a package file naming the entry in any error
+fn parse_pkginfo(entry: &str, data: &str) -> Result<Package> {
+    let mut pkg = Package::default();
+
+    for x in assignments(entry, data) {
+        let x = x?;
+        let value = x.values[0];
+        match x.name {
+            "pkgname" => pkg.name = value.to_owned(),
+            "pkgbase" => pkg.base = value.to_owned(),
+            "pkgver" => pkg.version = Version::from(value),
+            "pkgdesc" => pkg.desc = value.to_owned(),
+            "url" => pkg.url = value.to_owned(),
+            "builddate" => pkg.build_date = value.to_owned(),
+            "packager" => pkg.packager = value.to_owned(),
+            "size" => {
+                pkg.isize = parse_u64(value).map_err(|e| e.locate_assignment(entry, data, &x))?
+            }
+            "arch" => pkg.arch = value.to_owned(),
+            "license" => pkg.licenses.push(value.to_owned()),
+            "replaces" => pkg.replaces.push(Dependency::from(value)),
+            "group" => pkg.groups.push(value.to_owned()),
+            "conflict" => pkg.conflicts.push(Dependency::from(value)),
+            "provides" => pkg.provides.push(Dependency::from(value)),
+            "backup" => pkg.backup.push(Backup {
+                path: value.to_owned(),
+                md5sum: Default::default(),
+            }),
+            "depend" => pkg.depends.push(Dependency::from(value)),
+            "optdepend" => pkg.optional_depends.push(OptionalDependency::from(value)),
+            "makedepend" => pkg.make_depends.push(Dependency::from(value)),
+            "checkdepend" => pkg.check_depends.push(Dependency::from(value)),
+            _ => push_extra(&mut pkg.extra, &x),
+        }
+    }
+
+    Ok(pkg)
+}
+
+impl TryFrom<&str> for BuildInfo {
+    type Error = Error;
+
+    /// Parse `.BUILDINFO` text
+    fn try_from(data: &str) -> Result<Self> {
+        parse_buildinfo("", data)
+    }
+}
+
+/// Parse the `.BUILDINFO` of a package file naming the entry in any error
+fn parse_buildinfo(entry: &str, data: &str) -> Result<BuildInfo> {
+    let mut info = BuildInfo::default();
+
+    for x in assignments(entry, data) {
+        let x = x?;
+        let value = x.values[0];
+        match x.name {
+            "format" => info.format = value.to_owned(),
+            "pkgname" => info.name = value.to_owned(),
+            "pkgbase" => info.base = value.to_owned(),
+            "pkgver" => info.version = Version::from(value),
+            "pkgarch" => info.arch = value.to_owned(),
+            "pkgbuild_sha256sum" => info.pkgbuild_sha256sum = value.to_owned(),
+            "packager" => info.packager = value.to_owned(),
+            "builddate" => info.build_date = value.to_owned(),
+            "builddir" => info.build_dir = value.to_owned(),
+            "startdir" => info.start_dir = value.to_owned(),
+            "buildtool" => info.build_tool = value.to_owned(),
+            "buildtoolver" => info.build_tool_version = value.to_owned(),
+            "buildenv" => info.build_env.push(value.to_owned()),
+            "options" => info.options.push(value.to_owned()),
+            "installed" => info.installed.push(value.to_owned()),
+            _ => push_extra(&mut info.extra, &x),
+        }
+    }
+
+    Ok(info)
+}
+
+/// Check if the archive entry is package metadata rather than a payload file
+fn is_metadata(path: &str) -> bool {
+    matches!(
+        path.trim_start_matches("./"),
+        ".PKGINFO" | ".BUILDINFO" | ".MTREE"
+    )
+}
+
+/// Convert the data of an archive entry to text naming the package file and entry in
+/// any error e.g. `foo-1.0-1-x86_64.pkg.tar.zst/.PKGINFO`
+fn utf8(filename: &str, entry: &str, data: Vec<u8>) -> Result<String> {
+    String::from_utf8(data)
+        .map_err(|e| Error::PackageUtf8Conversion(format!("{}/{}: {}", filename, entry, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "gzip", feature = "zstd", feature = "xz"))]
+    #[test]
+    fn test_open() {
+        for ext in &["zst", "xz", "gz"] {
+            let path = format!("tests/pkg/foo-1.0-1-x86_64.pkg.tar.{}", ext);
+            let file = PackageFile::open(&path).unwrap();
+            let pkg = &file.package;
+            assert_eq!(pkg.name, "foo");
+            assert_eq!(pkg.base, "foo");
+            assert_eq!(pkg.version, Version::from("1.0-1"));
+            assert_eq!(pkg.desc, "A test package = with equals");
+            assert_eq!(pkg.isize, 12345);
+            assert_eq!(pkg.licenses, vec!["MIT", "custom"]);
+            assert_eq!(pkg.replaces, vec![Dependency::from("oldfoo")]);
+            assert_eq!(pkg.groups, vec!["examples"]);
+            assert_eq!(pkg.provides, vec![Dependency::from("libfoo.so=1-64")]);
+            assert_eq!(pkg.depends.len(), 2);
+            assert_eq!(
+                pkg.optional_depends,
+                vec![OptionalDependency::from("bash: for the helper script")]
+            );
+            assert_eq!(pkg.backup[0].path, "etc/foo.conf");
+            assert_eq!(
+                pkg.extra,
+                vec![(
+                    "makepkgopt".to_owned(),
+                    vec!["strip".to_owned(), "!debug".to_owned()]
+                )]
+            );
+
+            assert_eq!(pkg.filename, format!("foo-1.0-1-x86_64.pkg.tar.{}", ext));
+            assert_eq!(pkg.size, std::fs::metadata(&path).unwrap().len());
+            assert_eq!(pkg.sha256sum.len(), 64);
+            assert!(pkg.owns("/usr/share/doc/foo/read me.txt"));
+            assert_eq!(
+                pkg.files
+                    .iter()
+                    .map(|x| x.path.as_str())
+                    .collect::<Vec<_>>()[..3],
+                ["etc/", "etc/foo.conf", "usr/"]
+            );
+            assert_eq!(pkg.files.len(), 12);
+
+            let info = file.build_info.unwrap();
+            assert_eq!(info.format, "2");
+            assert_eq!(info.build_tool, "devtools");
+            assert_eq!(info.build_env, vec!["!distcc", "color"]);
+            assert_eq!(info.installed.len(), 2);
+
+            assert_eq!(file.mtree.len(), 14);
+            let link = file
+                .mtree
+                .iter()
+                .find(|x| x.path == "usr/lib/libfoo.so")
+                .unwrap();
+            assert_eq!(link.kind, MtreeKind::Link);
+            assert_eq!(link.mode, 0o777);
+            let readme = file.mtree.last().unwrap();
+            assert_eq!(readme.path, "usr/share/doc/foo/read me.txt");
+            assert_eq!(readme.size, 8);
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_checksums() {
+        let file = PackageFile::open("tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst").unwrap();
+        assert_eq!(file.package.md5sum, "47271b4b3c729b3b7b805be5e43c6f67");
+        assert_eq!(
+            file.package.sha256sum,
+            "ae17cbd10c2af2c353f3d1ddbfda50aee8907d2ffb752b12e1bb09108007a84c"
+        );
+    }
+
+    #[test]
+    fn test_buildinfo() {
+        let info = BuildInfo::try_from("format = 2\npkgname = foo\n\nbuildenv = ccache\n").unwrap();
+        assert_eq!(info.name, "foo");
+        assert_eq!(info.build_env, vec!["ccache"]);
+
+        let err = BuildInfo::try_from("format = 2\npkgname foo\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "package parse failure: 2:1: \"pkgname foo\": package property invalid: pkgname foo"
+        );
+    }
+
+    #[test]
+    fn test_pkginfo_errors() {
+        let err = parse_pkginfo(".PKGINFO", "pkgname = foo\nsize = big\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "package parse failure: .PKGINFO:2:8: size \"big\": invalid digit found in string"
+        );
+        let err = utf8("foo.pkg.tar.zst", ".PKGINFO", vec![0xff]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("foo.pkg.tar.zst/.PKGINFO: invalid utf-8"));
+    }
+
+    #[test]
+    fn test_open_missing() {
+        assert!(matches!(
+            PackageFile::open("tests/pkg/missing.pkg.tar.zst"),
+            Err(Error::PackageLoad(..))
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::prelude::*;
+use std::collections::HashSet;
+
+/// `Resolved` is a package selected by the resolver along with its repository
+#[derive(Debug, Clone, Copy)]
+pub struct Resolved<'a> {
+    pub repo: &'a str,
+    pub package: &'a Package,
+}
+
+/// `Provider` chooses between several packages providing the same dependency by
+/// returning the index of the chosen candidate. Candidates are in priority order.
+pub type Provider<'a> = Box<dyn Fn(&Dependency, &[Resolved<'a>]) -> usize + 'a>;
+
+/// `Resolver` computes the closure of runtime dependencies for a set of targets
+/// against repositories in priority order e.g. those from `DatabaseSet::index`.
+pub struct Resolver<'a> {
+    repos: &'a [RepoIndex],
+    provider: Provider<'a>,
+}
+impl<'a> Resolver<'a> {
+    /// Create a resolver over the given repositories, earlier repositories take
+    /// priority. By default the first provider in priority order is chosen.
+    pub fn new(repos: &'a [RepoIndex]) -> Self {
+        Self {
+            repos,
+            provider: Box::new(|_, _| 0),
+        }
+    }
+
+    /// Set the callback used to choose between several providers of a dependency
+    pub fn with_provider(
+        mut self,
+        provider: impl Fn(&Dependency, &[Resolved<'a>]) -> usize + 'a,
+    ) -> Self {
+        self.provider = Box::new(provider);
+        self
+    }
+
+    /// Resolve the targets e.g. `bash` or `glibc>=2.33` along with all of their
+    /// transitive dependencies. Packages are ordered with dependencies first.
+    pub fn resolve(&self, targets: &[&str]) -> Result<Vec<Resolved<'a>>> {
+        let mut state = State::default();
+        for target in targets {
+            self.visit(&Dependency::from(*target), &mut state)?;
+        }
+        Ok(state.resolved)
+    }
+
+    /// Resolve a dependency and its dependencies depth first
+    fn visit(&self, dep: &Dependency, state: &mut State<'a>) -> Result<()> {
+        // Already satisfied by a selected or in progress package
+        if state.selected.iter().any(|x| dep.satisfied_by(x.package)) {
+            return Ok(());
+        }
+
+        let candidate = self.satisfier(dep, &state.chain)?;
+
+        // A package of the same name is already selected but doesn't meet the
+        // constraint and only one version of a package can be installed
+        if state
+            .selected
+            .iter()
+            .any(|x| x.package.name == candidate.package.name)
+        {
+            return Err(Error::DependencyUnsatisfiable(
+                dep.to_string(),
+                state.chain.to_owned(),
+            ));
+        }
+        for x in &state.selected {
+            if conflicts(x.package, candidate.package) || conflicts(candidate.package, x.package) {
+                return Err(Error::PackageConflict(
+                    x.package.name.to_owned(),
+                    candidate.package.name.to_owned(),
+                    state.chain.to_owned(),
+                ));
+            }
+        }
+
+        state.selected.push(candidate);
+        state.chain.push(candidate.package.name.to_owned());
+        for x in &candidate.package.depends {
+            self.visit(x, state)?;
+        }
+        state.chain.pop();
+        if state.done.insert(candidate.package.name.to_owned()) {
+            state.resolved.push(candidate);
+        }
+        Ok(())
+    }
+
+    /// Find the package to satisfy the dependency. A package of the same name is
+    /// preferred in repository order, otherwise the providers are considered.
+    fn satisfier(&self, dep: &Dependency, chain: &[String]) -> Result<Resolved<'a>> {
+        let by_name = self.repos.iter().find_map(|repo| {
+            repo.get(&dep.name)
+                .ok()
+                .filter(|x| dep.satisfied_by(x))
+                .map(|x| Resolved {
+                    repo: repo.name(),
+                    package: x,
+                })
+        });
+        if let Some(x) = by_name {
+            return Ok(x);
+        }
+
+        let providers = self
+            .repos
+            .iter()
+            .flat_map(|repo| {
+                repo.provides(&dep.name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|x| dep.satisfied_by(x))
+                    .map(move |x| Resolved {
+                        repo: repo.name(),
+                        package: x,
+                    })
+            })
+            .collect::<Vec<_>>();
+        match providers.len() {
+            0 => Err(Error::DependencyUnsatisfiable(
+                dep.to_string(),
+                chain.to_owned(),
+            )),
+            1 => Ok(providers[0]),
+            _ => {
+                let i = (self.provider)(dep, &providers);
+                providers.get(i).copied().ok_or_else(|| {
+                    Error::DependencyUnsatisfiable(dep.to_string(), chain.to_owned())
+                })
+            }
+        }
+    }
+}
+
+/// Resolution state shared across the depth first search
+#[derive(Default)]
+struct State<'a> {
+    selected: Vec<Resolved<'a>>, // Packages chosen so far including those in progress
+    resolved: Vec<Resolved<'a>>, // Packages fully resolved in dependency order
+    done: HashSet<String>,       // Names of the fully resolved packages
+    chain: Vec<String>,          // Names of the packages currently being resolved
+}
+
+/// Check if the package declares a conflict satisfied by the other package
+fn conflicts(pkg: &Package, other: &Package) -> bool {
+    pkg.name != other.name && pkg.conflicts.iter().any(|x| x.satisfied_by(other))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{package as pkg, repo};
+
+    fn provider(mut pkg: Package, provides: &[&str]) -> Package {
+        pkg.provides = provides.iter().map(|x| Dependency::from(*x)).collect();
+        pkg
+    }
+
+    fn repos() -> Vec<RepoIndex> {
+        let core = vec![
+            pkg("glibc", "2.32-5", &[]),
+            provider(pkg("bash", "5.1.004-1", &["glibc", "readline>=8"]), &["sh"]),
+            provider(
+                pkg("readline", "8.0.004-1", &["glibc"]),
+                &["libreadline.so=8-64"],
+            ),
+            pkg("coreutils", "8.32-1", &["glibc", "sh"]),
+        ];
+        let extra = vec![
+            pkg("glibc", "2.33-1", &[]),
+            provider(pkg("dash", "0.5.11.2-1", &["glibc"]), &["sh"]),
+            provider(pkg("zsh", "5.8-1", &["glibc"]), &["sh"]),
+            pkg("python", "3.9.1-1", &["glibc>=2.33", "libreadline.so=8-64"]),
+            pkg("broken", "1-1", &["missing>=2"]),
+        ];
+        vec![repo("core", core), repo("extra", extra)]
+    }
+
+    fn names(resolved: &[Resolved]) -> Vec<String> {
+        resolved
+            .iter()
+            .map(|x| format!("{}/{}", x.repo, x.package.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let repos = repos();
+        let resolved = Resolver::new(&repos).resolve(&["coreutils"]).unwrap();
+        assert_eq!(
+            names(&resolved),
+            vec!["core/glibc", "core/readline", "core/bash", "core/coreutils"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_versioned() {
+        // glibc>=2.33 is only satisfied by the lower priority repository
+        let repos = repos();
+        let resolved = Resolver::new(&repos).resolve(&["python"]).unwrap();
+        assert_eq!(
+            names(&resolved),
+            vec!["extra/glibc", "core/readline", "extra/python"]
+        );
+    }
+
+    #[test]
+    fn test_provider_selection() {
+        let repos = repos();
+        let resolver = Resolver::new(&repos).with_provider(|dep, candidates| {
+            assert_eq!(dep.name, "sh");
+            candidates
+                .iter()
+                .position(|x| x.package.name == "dash")
+                .unwrap()
+        });
+        let resolved = resolver.resolve(&["coreutils"]).unwrap();
+        assert_eq!(
+            names(&resolved),
+            vec!["core/glibc", "extra/dash", "core/coreutils"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_resolve_core_db() {
+        let dbs = DatabaseSet::default()
+            .with_database(Database::default().with_path("tests/sync/core.db"));
+        let repos = dbs.index().unwrap();
+        let resolved = Resolver::new(&repos).resolve(&["coreutils"]).unwrap();
+        let names = resolved
+            .iter()
+            .map(|x| x.package.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names.last(), Some(&"coreutils"));
+        for x in &["glibc", "acl", "attr", "gmp", "libcap", "openssl"] {
+            assert!(names.contains(x), "{}", x);
+        }
+        assert!(resolved.iter().all(|x| x.repo == "core"));
+    }
+
+    #[test]
+    fn test_unsatisfiable() {
+        let repos = repos();
+        let err = Resolver::new(&repos).resolve(&["broken"]).unwrap_err();
+        assert!(
+            matches!(err, Error::DependencyUnsatisfiable(ref dep, ref chain) if dep == "missing>=2" && chain == &["broken"])
+        );
+        assert_eq!(
+            err.to_string(),
+            "unsatisfiable dependency: missing>=2 (required by broken)"
+        );
+        assert!(matches!(
+            Resolver::new(&repos).resolve(&["glibc>=3"]),
+            Err(Error::DependencyUnsatisfiable(_, ref chain)) if chain.is_empty()
+        ));
+
+        // A version constraint that the already selected package can't meet
+        let err = Resolver::new(&repos)
+            .resolve(&["coreutils", "python"])
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::DependencyUnsatisfiable(ref dep, ref chain) if dep == "glibc>=2.33" && chain == &["python"])
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut repos = repos();
+        let mut pkg = pkg("busybox", "1.32-1", &["coreutils"]);
+        pkg.conflicts = vec![Dependency::from("bash")];
+        repos[1].insert(pkg);
+
+        let err = Resolver::new(&repos).resolve(&["busybox"]).unwrap_err();
+        assert!(
+            matches!(err, Error::PackageConflict(ref a, ref b, ref chain) if a == "busybox" && b == "bash" && chain == &["busybox", "coreutils"])
+        );
+    }
+}
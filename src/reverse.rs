@@ -0,0 +1,196 @@
+use crate::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::iter::FromIterator;
+
+/// `DependencyKind` is the package field a dependency was declared in
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DependencyKind {
+    Depends,
+    MakeDepends,
+    CheckDepends,
+    OptDepends,
+}
+
+/// `ReverseIndex` answers which packages depend on a given package e.g. pacman's
+/// `Required By` and `Optional For`. Dependencies are matched against package names
+/// and provisions so `sh` counts towards `bash`.
+#[derive(Debug, Default, Clone)]
+pub struct ReverseIndex {
+    dependents: HashMap<String, Vec<(DependencyKind, String)>>, // Package name to dependents
+}
+impl ReverseIndex {
+    /// Index the packages of the given repositories
+    pub fn new(repos: &[RepoIndex]) -> Self {
+        repos.iter().flatten().collect()
+    }
+
+    /// Packages that directly depend on the given package at runtime
+    pub fn required_by(&self, name: &str) -> Vec<&str> {
+        self.direct(name, DependencyKind::Depends)
+    }
+
+    /// Packages that optionally depend on the given package
+    pub fn optional_for(&self, name: &str) -> Vec<&str> {
+        self.direct(name, DependencyKind::OptDepends)
+    }
+
+    /// Packages that directly depend on the given package with the given kind
+    pub fn direct(&self, name: &str, kind: DependencyKind) -> Vec<&str> {
+        let mut names = BTreeSet::new();
+        self.collect(name, kind, &mut names);
+        names.into_iter().collect()
+    }
+
+    /// Packages affected by removing the given package. The runtime dependents are
+    /// followed transitively and the packages declaring a dependency of the given
+    /// kind on any of them are returned e.g. everything whose build breaks.
+    pub fn transitive(&self, name: &str, kind: DependencyKind) -> Vec<&str> {
+        // Closure of the runtime dependents
+        let mut closure = BTreeSet::new();
+        self.collect(name, DependencyKind::Depends, &mut closure);
+        let mut pending = closure.iter().copied().collect::<Vec<_>>();
+        while let Some(x) = pending.pop() {
+            let mut found = BTreeSet::new();
+            self.collect(x, DependencyKind::Depends, &mut found);
+            pending.extend(found.into_iter().filter(|x| closure.insert(*x)));
+        }
+        closure.retain(|x| *x != name);
+        if kind == DependencyKind::Depends {
+            return closure.into_iter().collect();
+        }
+
+        let mut names = BTreeSet::new();
+        self.collect(name, kind, &mut names);
+        for x in closure {
+            self.collect(x, kind, &mut names);
+        }
+        names.into_iter().collect()
+    }
+
+    fn collect<'a>(&'a self, name: &str, kind: DependencyKind, names: &mut BTreeSet<&'a str>) {
+        let dependents = self.dependents.get(name).into_iter().flatten();
+        names.extend(dependents.filter(|x| x.0 == kind).map(|x| x.1.as_str()));
+    }
+}
+
+impl<'a> FromIterator<&'a Package> for ReverseIndex {
+    fn from_iter<T: IntoIterator<Item = &'a Package>>(iter: T) -> Self {
+        let packages = iter.into_iter().collect::<Vec<_>>();
+
+        // Names and provisions to the packages that may satisfy them
+        let mut satisfiers: HashMap<&str, Vec<&Package>> = HashMap::new();
+        for pkg in &packages {
+            satisfiers.entry(&pkg.name).or_default().push(pkg);
+            for x in &pkg.provides {
+                satisfiers.entry(&x.name).or_default().push(pkg);
+            }
+        }
+
+        let mut index = Self::default();
+        for pkg in &packages {
+            let optional = pkg
+                .optional_depends
+                .iter()
+                .map(|x| x.dependency.to_owned())
+                .collect::<Vec<_>>();
+            let deps = [
+                (DependencyKind::Depends, &pkg.depends),
+                (DependencyKind::MakeDepends, &pkg.make_depends),
+                (DependencyKind::CheckDepends, &pkg.check_depends),
+                (DependencyKind::OptDepends, &optional),
+            ];
+            for (kind, deps) in deps.iter() {
+                for dep in deps.iter() {
+                    let found = satisfiers.get(dep.name.as_str()).into_iter().flatten();
+                    for x in found.filter(|x| dep.satisfied_by(x)) {
+                        let entry = (*kind, pkg.name.to_owned());
+                        let dependents = index.dependents.entry(x.name.to_owned()).or_default();
+                        if !dependents.contains(&entry) {
+                            dependents.push(entry);
+                        }
+                    }
+                }
+            }
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{package, repo};
+
+    fn index() -> ReverseIndex {
+        let mut openssl = package("openssl-1.1", "1.0-1", &["glibc"]);
+        openssl.provides = vec![Dependency::from("libssl.so=1.1-64")];
+        let mut python = package("python", "1.0-1", &["glibc", "libssl.so=1.1-64"]);
+        python.optional_depends = vec![OptionalDependency::from("sqlite: for sqlite3")];
+        let mut app = package("app", "1.0-1", &["python"]);
+        app.make_depends = vec![Dependency::from("openssl-1.1")];
+        let mut lib = package("lib", "1.0-1", &["glibc"]);
+        lib.make_depends = vec![Dependency::from("python>=1")];
+        lib.check_depends = vec![Dependency::from("app")];
+        let mut tool = package("tool", "1.0-1", &["openssl-1.1>=2"]);
+        tool.optional_depends = vec![OptionalDependency::from("openssl-1.1")];
+
+        let core = vec![
+            package("glibc", "1.0-1", &[]),
+            openssl,
+            package("sqlite", "1.0-1", &["glibc"]),
+        ];
+        let extra = vec![python, app, lib, tool];
+        let repos = vec![repo("core", core), repo("extra", extra)];
+        ReverseIndex::new(&repos)
+    }
+
+    #[test]
+    fn test_direct() {
+        let index = index();
+        assert_eq!(
+            index.required_by("glibc"),
+            vec!["lib", "openssl-1.1", "python", "sqlite"]
+        );
+
+        // Unsatisfied version constraints are not reverse dependencies
+        assert_eq!(index.required_by("openssl-1.1"), vec!["python"]);
+        assert_eq!(index.optional_for("openssl-1.1"), vec!["tool"]);
+        assert_eq!(index.optional_for("sqlite"), vec!["python"]);
+        assert_eq!(
+            index.direct("openssl-1.1", DependencyKind::MakeDepends),
+            vec!["app"]
+        );
+        assert_eq!(
+            index.direct("app", DependencyKind::CheckDepends),
+            vec!["lib"]
+        );
+        assert!(index.required_by("missing").is_empty());
+    }
+
+    #[test]
+    fn test_transitive() {
+        let index = index();
+        assert_eq!(
+            index.transitive("openssl-1.1", DependencyKind::Depends),
+            vec!["app", "python"]
+        );
+        assert_eq!(
+            index.transitive("openssl-1.1", DependencyKind::MakeDepends),
+            vec!["app", "lib"]
+        );
+        assert_eq!(
+            index.transitive("openssl-1.1", DependencyKind::CheckDepends),
+            vec!["lib"]
+        );
+        assert_eq!(
+            index.transitive("openssl-1.1", DependencyKind::OptDepends),
+            vec!["tool"]
+        );
+        assert_eq!(
+            index.transitive("glibc", DependencyKind::Depends),
+            vec!["app", "lib", "openssl-1.1", "python", "sqlite"]
+        );
+    }
+}
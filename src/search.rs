@@ -0,0 +1,373 @@
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// `SearchField` is a package field a `Search` matches against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchField {
+    Name,
+    Desc,
+    Provides,
+    Groups,
+}
+
+/// Every field a `Search` can match against
+pub const SEARCH_FIELDS: &[SearchField] = &[
+    SearchField::Name,
+    SearchField::Desc,
+    SearchField::Provides,
+    SearchField::Groups,
+];
+
+/// `Rank` orders search results by how well the package matched, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rank {
+    ExactName,  // The whole name matched
+    NamePrefix, // The name starts with the match
+    Name,       // The name contains the match
+    Provides,   // A provided name matched
+    Groups,     // A group matched
+    Desc,       // The description matched
+}
+
+/// `Search` finds packages by name and description ranking the best matches first.
+/// The pattern is a case sensitive substring by default, set `with_ignore_case` and
+/// `with_regex` to match a case insensitive regex as `pacman -Ss` does.
+#[derive(Debug, Clone)]
+pub struct Search {
+    pattern: String,          // Substring or regex to match
+    fields: Vec<SearchField>, // Fields to match against
+    ignore_case: bool,        // Match regardless of case
+    #[cfg(feature = "regex")]
+    regex: bool, // Treat the pattern as a regex
+}
+impl Search {
+    /// Create a search for the given pattern
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            fields: vec![SearchField::Name, SearchField::Desc],
+            ignore_case: false,
+            #[cfg(feature = "regex")]
+            regex: false,
+        }
+    }
+
+    /// Set the fields to match against e.g. `SEARCH_FIELDS` for all of them
+    pub fn with_fields(mut self, fields: &[SearchField]) -> Self {
+        self.fields = fields.to_vec();
+        self
+    }
+
+    /// Match regardless of case as `pacman -Ss` does
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Treat the pattern as a regex e.g. `^python-` rather than a substring, requires
+    /// the `regex` feature
+    #[cfg(feature = "regex")]
+    pub fn with_regex(mut self, regex: bool) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Search the repositories returning the matches ranked best first. Matches of
+    /// the same rank are kept in repository and database order.
+    pub fn run<'a>(&self, repos: &'a [RepoIndex]) -> Result<Vec<SearchResult<'a>>> {
+        let matcher = self.matcher()?;
+        let mut results = Vec::new();
+        for repo in repos {
+            for pkg in repo.iter() {
+                if let Some(rank) = self.rank(&matcher, pkg) {
+                    results.push(SearchResult {
+                        repo: repo.name(),
+                        package: pkg,
+                        rank,
+                    });
+                }
+            }
+        }
+        results.sort_by_key(|x| x.rank);
+        Ok(results)
+    }
+
+    /// Best rank of the package if any field matched
+    fn rank(&self, matcher: &Matcher, pkg: &Package) -> Option<Rank> {
+        self.fields
+            .iter()
+            .filter_map(|field| match field {
+                SearchField::Name => matcher.find(&pkg.name).map(|x| match x {
+                    Position::Whole => Rank::ExactName,
+                    Position::Start => Rank::NamePrefix,
+                    Position::Inside => Rank::Name,
+                }),
+                SearchField::Desc => matcher.find(&pkg.desc).map(|_| Rank::Desc),
+                SearchField::Provides => pkg
+                    .provides
+                    .iter()
+                    .find_map(|x| matcher.find(&x.name))
+                    .map(|_| Rank::Provides),
+                SearchField::Groups => pkg
+                    .groups
+                    .iter()
+                    .find_map(|x| matcher.find(x))
+                    .map(|_| Rank::Groups),
+            })
+            .min()
+    }
+
+    #[cfg(feature = "regex")]
+    fn matcher(&self) -> Result<Matcher> {
+        if !self.regex {
+            return Ok(self.substring());
+        }
+        regex::RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map(Matcher::Regex)
+            .map_err(|e| Error::SearchPattern(e.to_string()))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn matcher(&self) -> Result<Matcher> {
+        Ok(self.substring())
+    }
+
+    fn substring(&self) -> Matcher {
+        let pattern = if self.ignore_case {
+            self.pattern.to_lowercase()
+        } else {
+            self.pattern.to_owned()
+        };
+        Matcher::Substring(pattern, self.ignore_case)
+    }
+}
+
+/// `SearchResult` is a package matched by a `Search`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult<'a> {
+    pub repo: &'a str,        // Repository of the package e.g. `core`
+    pub package: &'a Package, // Matched package
+    pub rank: Rank,           // How well the package matched
+}
+
+/// `SearchFormatter` writes search results just like `pacman -Ss` including the
+/// `[installed]` markers when the installed packages are given
+#[derive(Debug, Default, Clone)]
+pub struct SearchFormatter {
+    installed: HashMap<String, Version>, // Installed package versions by name
+}
+impl SearchFormatter {
+    /// Set the installed packages e.g. from the `LocalDatabase`
+    pub fn with_installed<'a>(mut self, packages: impl IntoIterator<Item = &'a Package>) -> Self {
+        self.installed = packages
+            .into_iter()
+            .map(|x| (x.name.to_owned(), x.version.to_owned()))
+            .collect();
+        self
+    }
+
+    /// Write the results with the description of each package indented below it
+    pub fn write(&self, out: &mut impl Write, results: &[SearchResult]) -> io::Result<()> {
+        for x in results {
+            let pkg = x.package;
+            write!(out, "{}/{} {}", x.repo, pkg.name, pkg.version)?;
+            if !pkg.groups.is_empty() {
+                write!(out, " ({})", pkg.groups.join(" "))?;
+            }
+            match self.installed.get(&pkg.name) {
+                Some(_) if x.repo == "local" => {}
+                Some(version) if *version == pkg.version => write!(out, " [installed]")?,
+                Some(version) => write!(out, " [installed: {}]", version)?,
+                None => {}
+            }
+            writeln!(out)?;
+            writeln!(out, "    {}", pkg.desc)?;
+        }
+        Ok(())
+    }
+
+    /// Format the results as text
+    pub fn format(&self, results: &[SearchResult]) -> String {
+        let mut out = Vec::new();
+        let _ = self.write(&mut out, results);
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+/// `Position` is where a match was found in a value
+enum Position {
+    Whole,
+    Start,
+    Inside,
+}
+
+/// `Matcher` is a compiled search pattern
+enum Matcher {
+    Substring(String, bool), // Pattern lower cased when ignoring case
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+impl Matcher {
+    fn find(&self, value: &str) -> Option<Position> {
+        let (start, end, len) = match *self {
+            Matcher::Substring(ref pattern, ignore_case) => {
+                let value = if ignore_case {
+                    value.to_lowercase()
+                } else {
+                    value.to_owned()
+                };
+                let start = value.find(pattern.as_str())?;
+                (start, start + pattern.len(), value.len())
+            }
+            #[cfg(feature = "regex")]
+            Matcher::Regex(ref regex) => {
+                let x = regex.find(value)?;
+                (x.start(), x.end(), value.len())
+            }
+        };
+        Some(match (start, end) {
+            (0, x) if x == len => Position::Whole,
+            (0, _) => Position::Start,
+            _ => Position::Inside,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{package, repo};
+
+    fn repos() -> Vec<RepoIndex> {
+        let described = |name: &str, desc: &str| Package {
+            desc: desc.to_owned(),
+            ..package(name, "1.0-1", &[])
+        };
+        let mut sh = described("bash", "The GNU Bourne Again shell");
+        sh.provides = vec![Dependency::from("sh")];
+        sh.groups = vec!["base".to_owned()];
+        let core = vec![
+            described(
+                "zsh-completions",
+                "Additional completion definitions for Zsh",
+            ),
+            described(
+                "zsh",
+                "A very advanced and programmable command interpreter (shell)",
+            ),
+            sh,
+        ];
+        let extra = vec![
+            described("fish", "Smart and user friendly shell"),
+            described("oh-zsh", ""),
+        ];
+        vec![repo("core", core), repo("extra", extra)]
+    }
+
+    fn names(results: &[SearchResult]) -> Vec<String> {
+        results
+            .iter()
+            .map(|x| format!("{}/{}", x.repo, x.package.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_rank() {
+        let repos = repos();
+        let results = Search::new("zsh").run(&repos).unwrap();
+        assert_eq!(
+            names(&results),
+            vec!["core/zsh", "core/zsh-completions", "extra/oh-zsh"]
+        );
+        assert_eq!(results[0].rank, Rank::ExactName);
+        assert_eq!(results[1].rank, Rank::NamePrefix);
+        assert_eq!(results[2].rank, Rank::Name);
+
+        let results = Search::new("Zsh").run(&repos).unwrap();
+        assert_eq!(names(&results), vec!["core/zsh-completions"]);
+        assert_eq!(results[0].rank, Rank::Desc);
+
+        let results = Search::new("Zsh")
+            .with_ignore_case(true)
+            .run(&repos)
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].package.name, "zsh");
+    }
+
+    #[test]
+    fn test_fields() {
+        let repos = repos();
+        let results = Search::new("shell").run(&repos).unwrap();
+        assert_eq!(names(&results), vec!["core/zsh", "core/bash", "extra/fish"]);
+
+        let search = Search::new("sh").with_fields(&[SearchField::Provides]);
+        assert_eq!(names(&search.run(&repos).unwrap()), vec!["core/bash"]);
+        let search = Search::new("base").with_fields(SEARCH_FIELDS);
+        let results = search.run(&repos).unwrap();
+        assert_eq!(names(&results), vec!["core/bash"]);
+        assert_eq!(results[0].rank, Rank::Groups);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let repos = repos();
+        let search = Search::new("^(z|f)").with_regex(true);
+        assert_eq!(
+            names(&search.run(&repos).unwrap()),
+            vec!["core/zsh-completions", "core/zsh", "extra/fish"]
+        );
+        let search = Search::new("^ZSH$").with_regex(true).with_ignore_case(true);
+        let results = search.run(&repos).unwrap();
+        assert_eq!(names(&results), vec!["core/zsh"]);
+        assert_eq!(results[0].rank, Rank::ExactName);
+        assert!(matches!(
+            Search::new("(").with_regex(true).run(&repos),
+            Err(Error::SearchPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_format() {
+        let repos = repos();
+        let results = Search::new("sh")
+            .with_fields(&[SearchField::Name])
+            .run(&repos)
+            .unwrap();
+        let installed = vec![package("bash", "1.0-1", &[]), package("zsh", "0.9-1", &[])];
+        let formatter = SearchFormatter::default().with_installed(&installed);
+        assert_eq!(
+            formatter.format(&results[..3]),
+            "core/zsh-completions 1.0-1\n    \
+             Additional completion definitions for Zsh\n\
+             core/zsh 1.0-1 [installed: 0.9-1]\n    \
+             A very advanced and programmable command interpreter (shell)\n\
+             core/bash 1.0-1 (base) [installed]\n    \
+             The GNU Bourne Again shell\n"
+        );
+    }
+
+    #[test]
+    fn test_format_installed_version() {
+        // Equal by vercmp but not the same version
+        let repos = vec![repo("core", vec![package("zsh", "1.1-1", &[])])];
+        let results = Search::new("zsh").run(&repos).unwrap();
+        let installed = vec![package("zsh", "1.01-1", &[])];
+        let formatter = SearchFormatter::default().with_installed(&installed);
+        assert_eq!(
+            formatter.format(&results),
+            "core/zsh 1.1-1 [installed: 1.01-1]\n    \n"
+        );
+
+        let installed = vec![package("zsh", "1.1-1", &[])];
+        let formatter = SearchFormatter::default().with_installed(&installed);
+        assert_eq!(
+            formatter.format(&results),
+            "core/zsh 1.1-1 [installed]\n    \n"
+        );
+    }
+}
@@ -0,0 +1,605 @@
+use crate::error::*;
+use crate::package::Package;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+pub(crate) const SIGNATURE: u8 = 2;
+
+/// `Signature` is a decoded OpenPGP version 4 signature packet e.g. from `%PGPSIG%`
+/// or a detached `.sig` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Signature {
+    pub kind: u8,                                 // Signature type e.g. `0x00` for binary
+    pub public_key_algorithm: PublicKeyAlgorithm, // Algorithm of the signing key
+    pub hash_algorithm: HashAlgorithm,            // Algorithm used to hash the data
+    #[cfg_attr(feature = "serde", serde(with = "crate::date::seconds"))]
+    pub created: Option<u32>, // Creation time since the epoch
+    pub issuer: Option<String>,                   // Issuer key ID e.g. `95B26B15625F4991`
+    pub issuer_fingerprint: Option<String>,       // Issuer fingerprint as hex
+    pub expires: Option<u32>,                     // Seconds after creation it expires
+    pub key_expires: Option<u32>,                 // Seconds after key creation it expires
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) hashed: Vec<u8>, // Signature fields covered by the hash
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) digest_prefix: [u8; 2], // Leading bytes of the hash for a quick check
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) values: Vec<Vec<u8>>, // Algorithm specific signature values
+}
+impl Signature {
+    /// Decode the first signature in binary or ASCII armored OpenPGP data
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let data = dearmor(data)?;
+        packets(&data)?
+            .iter()
+            .find(|x| x.tag == SIGNATURE)
+            .ok_or_else(|| parse_error("no signature packet"))
+            .and_then(|x| Self::parse(x.body))
+    }
+
+    /// Decode a base64 signature e.g. the `%PGPSIG%` of a package
+    pub fn from_base64(data: &str) -> Result<Self> {
+        Self::from_bytes(&base64(data)?)
+    }
+
+    /// Issuer key ID taken from the fingerprint when not given directly
+    pub fn key_id(&self) -> Option<&str> {
+        self.issuer.as_deref().or_else(|| {
+            self.issuer_fingerprint
+                .as_deref()
+                .map(|x| &x[x.len().saturating_sub(16)..])
+        })
+    }
+
+    /// Decode the body of a signature packet
+    pub(crate) fn parse(body: &[u8]) -> Result<Self> {
+        let mut x = Bytes(body);
+        let version = x.u8()?;
+        if version != 4 {
+            return Err(parse_error(&format!(
+                "unsupported signature version {}",
+                version
+            )));
+        }
+        let kind = x.u8()?;
+        let public_key_algorithm = PublicKeyAlgorithm::from(x.u8()?);
+        let hash_algorithm = HashAlgorithm::from(x.u8()?);
+        let len = x.u16()? as usize;
+        let hashed_subpackets = x.take(len)?;
+        let hashed = body[..6 + len].to_vec();
+        let len = x.u16()? as usize;
+        let unhashed_subpackets = x.take(len)?;
+        let prefix = x.take(2)?;
+
+        let mut sig = Self {
+            kind,
+            public_key_algorithm,
+            hash_algorithm,
+            created: None,
+            issuer: None,
+            issuer_fingerprint: None,
+            expires: None,
+            key_expires: None,
+            hashed,
+            digest_prefix: [prefix[0], prefix[1]],
+            values: Vec::new(),
+        };
+        sig.subpackets(hashed_subpackets, true)?;
+        sig.subpackets(unhashed_subpackets, false)?;
+        while !x.0.is_empty() {
+            sig.values.push(x.mpi()?.to_vec());
+        }
+        Ok(sig)
+    }
+
+    /// Hashed fields followed by the version 4 trailer, which is appended to the
+    /// signed data when computing the hash
+    #[cfg(feature = "pgp")]
+    pub(crate) fn trailer(&self) -> Vec<u8> {
+        let mut trailer = self.hashed.to_owned();
+        trailer.extend_from_slice(&[4, 0xff]);
+        trailer.extend_from_slice(&(self.hashed.len() as u32).to_be_bytes());
+        trailer
+    }
+
+    /// Read the subpackets of interest. Only the hashed area is trusted for the
+    /// creation and expiry times, the issuer is only a hint and may be in either.
+    fn subpackets(&mut self, data: &[u8], hashed: bool) -> Result<()> {
+        let mut x = Bytes(data);
+        while !x.0.is_empty() {
+            let len = match x.u8()? as usize {
+                len if len < 192 => len,
+                len if len < 255 => ((len - 192) << 8) + x.u8()? as usize + 192,
+                _ => x.u32()? as usize,
+            };
+            let body = x.take(len)?;
+            // A zero validity period never expires
+            let period = |x: [u8; 4]| Some(u32::from_be_bytes(x)).filter(|x| *x != 0);
+            // The high bit of the type only flags the subpacket as critical
+            match body.split_first().map(|(kind, x)| (kind & 0x7f, x)) {
+                Some((2, &[a, b, c, d])) if hashed => {
+                    self.created = Some(u32::from_be_bytes([a, b, c, d]))
+                }
+                Some((3, &[a, b, c, d])) if hashed => self.expires = period([a, b, c, d]),
+                Some((9, &[a, b, c, d])) if hashed => self.key_expires = period([a, b, c, d]),
+                Some((16, id)) => self.issuer = Some(hex(id)),
+                Some((33, fpr)) if !fpr.is_empty() => {
+                    self.issuer_fingerprint = Some(hex(&fpr[1..]))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `PublicKeyAlgorithm` identifies the OpenPGP public key algorithm of a key or signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PublicKeyAlgorithm {
+    Rsa,
+    Dsa,
+    Ecdsa,
+    #[cfg_attr(feature = "serde", serde(rename = "eddsa"))]
+    EdDsa,
+    Other(u8),
+}
+impl From<u8> for PublicKeyAlgorithm {
+    fn from(id: u8) -> Self {
+        match id {
+            1..=3 => PublicKeyAlgorithm::Rsa,
+            17 => PublicKeyAlgorithm::Dsa,
+            19 => PublicKeyAlgorithm::Ecdsa,
+            22 => PublicKeyAlgorithm::EdDsa,
+            x => PublicKeyAlgorithm::Other(x),
+        }
+    }
+}
+impl fmt::Display for PublicKeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PublicKeyAlgorithm::Rsa => write!(f, "RSA"),
+            PublicKeyAlgorithm::Dsa => write!(f, "DSA"),
+            PublicKeyAlgorithm::Ecdsa => write!(f, "ECDSA"),
+            PublicKeyAlgorithm::EdDsa => write!(f, "EdDSA"),
+            PublicKeyAlgorithm::Other(x) => write!(f, "unknown ({})", x),
+        }
+    }
+}
+
+/// `HashAlgorithm` identifies the OpenPGP hash algorithm of a signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha224,
+    Other(u8),
+}
+impl From<u8> for HashAlgorithm {
+    fn from(id: u8) -> Self {
+        match id {
+            1 => HashAlgorithm::Md5,
+            2 => HashAlgorithm::Sha1,
+            8 => HashAlgorithm::Sha256,
+            9 => HashAlgorithm::Sha384,
+            10 => HashAlgorithm::Sha512,
+            11 => HashAlgorithm::Sha224,
+            x => HashAlgorithm::Other(x),
+        }
+    }
+}
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Sha1 => write!(f, "SHA1"),
+            HashAlgorithm::Sha256 => write!(f, "SHA256"),
+            HashAlgorithm::Sha384 => write!(f, "SHA384"),
+            HashAlgorithm::Sha512 => write!(f, "SHA512"),
+            HashAlgorithm::Sha224 => write!(f, "SHA224"),
+            HashAlgorithm::Other(x) => write!(f, "unknown ({})", x),
+        }
+    }
+}
+
+impl Package {
+    /// Decode the `%PGPSIG%` of the package if it has one
+    pub fn signature(&self) -> Result<Option<Signature>> {
+        match self.pgpsig.as_str() {
+            "" => Ok(None),
+            x => Signature::from_base64(x).map(Some),
+        }
+    }
+}
+
+/// `SignerAudit` groups packages by the key that signed them and flags packages whose
+/// `%PACKAGER%` isn't the owner of the signing key. Owners can be given for keys,
+/// otherwise the most common packager of each key's packages is assumed.
+#[derive(Debug, Default, Clone)]
+pub struct SignerAudit {
+    owners: Vec<(String, String)>, // Key ID or fingerprint and its packager
+}
+impl SignerAudit {
+    /// Set the packager owning the key e.g. `Jane Doe <jane@example.com>`. The key is
+    /// a key ID or fingerprint.
+    pub fn with_key(mut self, key: &str, packager: &str) -> Self {
+        self.owners.push((key.to_uppercase(), packager.to_owned()));
+        self
+    }
+
+    /// Set the owners of the keys in the keyring from their first user ID
+    #[cfg(feature = "pgp")]
+    pub fn with_keyring(mut self, keyring: &crate::pgp::Keyring) -> Self {
+        for key in keyring.keys() {
+            if let Some(uid) = key.user_ids.first() {
+                self.owners
+                    .push((key.fingerprint.to_owned(), uid.to_owned()));
+            }
+        }
+        self
+    }
+
+    /// Decode the signature of each package and build the report
+    pub fn report<'a>(&self, packages: impl IntoIterator<Item = &'a Package>) -> SignerReport {
+        let mut report = SignerReport::default();
+        let mut signed: BTreeMap<String, Vec<&Package>> = BTreeMap::new();
+        for pkg in packages {
+            match pkg.signature() {
+                Ok(Some(sig)) => {
+                    let key = sig.issuer_fingerprint.to_owned().or(sig.issuer);
+                    let key = key.unwrap_or_default();
+                    signed.entry(key).or_default().push(pkg);
+                }
+                Ok(None) => report.unsigned.push(pkg.name.to_owned()),
+                Err(e) => report.invalid.push((pkg.name.to_owned(), e.to_string())),
+            }
+        }
+
+        for (key, packages) in signed {
+            let packager = self.owner(&key).map(|x| x.to_owned()).or_else(|| {
+                let mut counts: HashMap<&str, usize> = HashMap::new();
+                for pkg in packages.iter() {
+                    *counts.entry(&pkg.packager).or_default() += 1;
+                }
+                counts
+                    .into_iter()
+                    .max_by(|x, y| x.1.cmp(&y.1).then(y.0.cmp(x.0)))
+                    .map(|x| x.0.to_owned())
+            });
+            for pkg in packages.iter() {
+                match packager {
+                    Some(ref x) if !same_packager(x, &pkg.packager) => {
+                        report.mismatches.push(PackagerMismatch {
+                            package: pkg.name.to_owned(),
+                            packager: pkg.packager.to_owned(),
+                            key: key.to_owned(),
+                            owner: x.to_owned(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            report.signers.push(Signer {
+                key,
+                packager,
+                packages: packages.iter().map(|x| x.name.to_owned()).collect(),
+            });
+        }
+        report
+    }
+
+    /// Packager owning the key given as a fingerprint or key ID
+    fn owner(&self, key: &str) -> Option<&str> {
+        self.owners
+            .iter()
+            .find(|(x, _)| !key.is_empty() && (x.ends_with(key) || key.ends_with(x.as_str())))
+            .map(|(_, packager)| packager.as_str())
+    }
+}
+
+/// `SignerReport` is the outcome of a `SignerAudit`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignerReport {
+    pub signers: Vec<Signer>,              // Signing keys sorted by key
+    pub mismatches: Vec<PackagerMismatch>, // Packages not signed by their packager
+    pub unsigned: Vec<String>,             // Packages without a `%PGPSIG%`
+    pub invalid: Vec<(String, String)>,    // Packages whose `%PGPSIG%` failed to decode
+}
+
+/// `Signer` is a signing key along with the packages it signed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Signer {
+    pub key: String,              // Fingerprint or the key ID for older signatures
+    pub packager: Option<String>, // Packager owning the key
+    pub packages: Vec<String>,    // Names of the packages signed
+}
+
+/// `PackagerMismatch` is a package signed with a key owned by another packager
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackagerMismatch {
+    pub package: String,  // Name of the package
+    pub packager: String, // `%PACKAGER%` of the package
+    pub key: String,      // Key the package was signed with
+    pub owner: String,    // Packager owning the key
+}
+
+/// Compare packagers by email when both have one e.g. `Jane Doe <jane@example.com>`
+fn same_packager(x: &str, y: &str) -> bool {
+    let email = |x: &str| {
+        x.split_once('<')
+            .and_then(|(_, x)| x.split_once('>'))
+            .map(|(x, _)| x.trim().to_lowercase())
+    };
+    match (email(x), email(y)) {
+        (Some(x), Some(y)) => x == y,
+        _ => x.trim() == y.trim(),
+    }
+}
+
+/// `Packet` is a single OpenPGP packet borrowing its body from the input
+pub(crate) struct Packet<'a> {
+    pub(crate) tag: u8,
+    pub(crate) body: &'a [u8],
+}
+
+/// Split binary OpenPGP data into packets. Keys and signatures never use partial
+/// body lengths so they are rejected.
+pub(crate) fn packets(mut data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    let mut packets = Vec::new();
+    while let Some(&ctb) = data.first() {
+        let mut x = Bytes(&data[1..]);
+        let (tag, len) = match ctb {
+            x if x & 0x80 == 0 => return Err(parse_error("invalid packet header")),
+            ctb if ctb & 0x40 != 0 => {
+                let len = match x.u8()? as usize {
+                    len if len < 192 => len,
+                    len if len < 224 => ((len - 192) << 8) + x.u8()? as usize + 192,
+                    255 => x.u32()? as usize,
+                    _ => return Err(parse_error("partial packet length")),
+                };
+                (ctb & 0x3f, len)
+            }
+            ctb => {
+                let len = match ctb & 3 {
+                    0 => x.u8()? as usize,
+                    1 => x.u16()? as usize,
+                    2 => x.u32()? as usize,
+                    _ => x.0.len(),
+                };
+                ((ctb >> 2) & 0x0f, len)
+            }
+        };
+        let body = x.take(len)?;
+        packets.push(Packet { tag, body });
+        data = x.0;
+    }
+    Ok(packets)
+}
+
+/// Strip ASCII armor if present returning the binary data of every armored block
+pub(crate) fn dearmor(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.starts_with(b"-----BEGIN PGP") {
+        return Ok(data.to_vec());
+    }
+    let text = std::str::from_utf8(data).map_err(|_| parse_error("invalid armor"))?;
+    let mut binary = Vec::new();
+    let mut block = String::new();
+    let mut body = false;
+    for line in text.lines().map(|x| x.trim()) {
+        if line.starts_with("-----BEGIN PGP") {
+            body = false;
+            block.clear();
+        } else if line.starts_with("-----END PGP") {
+            binary.extend(base64(&block)?);
+            block.clear();
+        } else if !body {
+            // Headers e.g. `Version:` end at the first blank line
+            body = line.is_empty();
+        } else if !line.starts_with('=') {
+            block.push_str(line);
+        }
+    }
+    Ok(binary)
+}
+
+/// Decode standard base64 ignoring whitespace
+pub(crate) fn base64(data: &str) -> Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in data
+        .bytes()
+        .filter(|x| !x.is_ascii_whitespace() && *x != b'=')
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(parse_error("invalid base64")),
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((acc >> bits) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+/// Upper case hex as used for key IDs and fingerprints
+pub(crate) fn hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{:02X}", x)).collect()
+}
+
+pub(crate) fn parse_error(msg: &str) -> Error {
+    Error::SignatureParse(msg.to_owned())
+}
+
+/// `Bytes` reads big endian fields from the front of a packet body
+pub(crate) struct Bytes<'a>(pub(crate) &'a [u8]);
+impl<'a> Bytes<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(parse_error("truncated packet"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        let x = self.take(2)?;
+        Ok(u16::from_be_bytes([x[0], x[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        let x = self.take(4)?;
+        Ok(u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    /// Read a multiprecision integer without its bit count prefix
+    pub(crate) fn mpi(&mut self) -> Result<&'a [u8]> {
+        let bits = self.u16()? as usize;
+        self.take(bits.div_ceil(8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixtures::{foo, PGPSIG};
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64("aGVs\nbG8h").unwrap(), b"hello!");
+        assert!(base64("aGVs*G8=").is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        let sig = Signature::from_base64(PGPSIG).unwrap();
+        assert_eq!(sig.kind, 0);
+        assert_eq!(sig.public_key_algorithm, PublicKeyAlgorithm::EdDsa);
+        assert_eq!(sig.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(sig.created, Some(1609977600));
+        assert_eq!(sig.issuer.as_deref(), Some("95B26B15625F4991"));
+        assert_eq!(
+            sig.issuer_fingerprint.as_deref(),
+            Some("5774B0B8EF47A7AED5598D3E95B26B15625F4991")
+        );
+        assert_eq!(sig.key_id(), Some("95B26B15625F4991"));
+        assert_eq!(sig.expires, None);
+        assert_eq!(sig.digest_prefix, [0xa0, 0x2c]);
+        assert_eq!(sig.values.len(), 2);
+
+        let data = std::fs::read("tests/pkg/foo-1.0-1-x86_64.pkg.tar.zst.sig").unwrap();
+        assert_eq!(Signature::from_bytes(&data).unwrap(), sig);
+
+        let data = std::fs::read("tests/pgp/sig-expired.sig").unwrap();
+        assert_eq!(Signature::from_bytes(&data).unwrap().expires, Some(86400));
+    }
+
+    #[test]
+    fn test_signature_invalid() {
+        assert!(matches!(
+            Signature::from_base64("aGVsbG8="),
+            Err(Error::SignatureParse(_))
+        ));
+        let data = base64(PGPSIG).unwrap();
+        assert!(Signature::from_bytes(&data[..40]).is_err());
+    }
+
+    #[test]
+    fn test_package_signature() {
+        let pkg = Package::default();
+        assert_eq!(pkg.signature().unwrap(), None);
+        assert_eq!(
+            foo().signature().unwrap().unwrap().key_id(),
+            Some("95B26B15625F4991")
+        );
+    }
+
+    #[test]
+    fn test_audit() {
+        let jane = "Jane Doe <jane@example.com>";
+        let package = |name: &str, packager: &str, pgpsig: &str| Package {
+            name: name.to_owned(),
+            packager: packager.to_owned(),
+            pgpsig: pgpsig.to_owned(),
+            ..foo()
+        };
+        let packages = vec![
+            package("foo", jane, PGPSIG),
+            package("bar", jane, PGPSIG),
+            package("baz", "John Roe <john@example.com>", PGPSIG),
+            package("qux", jane, ""),
+            package("quux", jane, "aGVsbG8="),
+        ];
+
+        let report = SignerAudit::default().report(&packages);
+        assert_eq!(report.unsigned, vec!["qux"]);
+        assert_eq!(report.invalid.len(), 1);
+        assert_eq!(report.invalid[0].0, "quux");
+        assert_eq!(
+            report.signers,
+            vec![Signer {
+                key: "5774B0B8EF47A7AED5598D3E95B26B15625F4991".to_owned(),
+                packager: Some(jane.to_owned()),
+                packages: vec!["foo".to_owned(), "bar".to_owned(), "baz".to_owned()],
+            }]
+        );
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].package, "baz");
+        assert_eq!(report.mismatches[0].owner, jane);
+
+        let report = SignerAudit::default()
+            .with_key("95b26b15625f4991", "John Roe <john@example.com>")
+            .report(&packages);
+        let names = report
+            .mismatches
+            .iter()
+            .map(|x| x.package.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_audit_db() {
+        let db = crate::db::Database::default().with_path("tests/sync/core.db");
+        let pkgs = db.packages().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        let report = SignerAudit::default().report(&pkgs);
+        assert!(report.unsigned.is_empty());
+        assert!(report.invalid.is_empty());
+        assert_eq!(report.signers.len(), 21);
+        let signed = report
+            .signers
+            .iter()
+            .map(|x| x.packages.len())
+            .sum::<usize>();
+        assert_eq!(signed, pkgs.len());
+        assert!(report
+            .mismatches
+            .iter()
+            .all(|x| x.key == "86CFFCA918CF3AF47147588051E8B148A9999C34"
+                || x.key == "E499C79F53C96A54E572FEE1C06086337C50773E"));
+    }
+}
@@ -0,0 +1,372 @@
+use crate::parser::{assignments, push_extra, Token, ValueError};
+use crate::prelude::*;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+/// Checksum arrays a `.SRCINFO` may give for its sources in the order makepkg uses
+pub const CHECKSUM_KEYS: &[&str] = &[
+    "cksums",
+    "md5sums",
+    "sha1sums",
+    "sha224sums",
+    "sha256sums",
+    "sha384sums",
+    "sha512sums",
+    "b2sums",
+];
+
+/// `SrcInfo` is the source metadata of a PKGBUILD from its `.SRCINFO`. Properties of
+/// the `pkgbase` section apply to every split package unless the package overrides
+/// them in its own `pkgname` section.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SrcInfo {
+    pub base: SrcInfoSection,          // The `pkgbase` section
+    pub packages: Vec<SrcInfoSection>, // The `pkgname` sections in the order given
+}
+
+/// `SrcInfoSection` is a `pkgbase` or `pkgname` section of a `.SRCINFO`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SrcInfoSection {
+    pub name: String,                           // Value of the section header
+    pub properties: Vec<(String, Vec<String>)>, // Properties in the order given
+}
+impl SrcInfoSection {
+    /// Values of the given property e.g. `depends_x86_64`. Returns `Some` with no
+    /// values for a property that was cleared e.g. `depends =`.
+    pub fn get(&self, key: &str) -> Option<&[String]> {
+        self.properties
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, x)| x.as_slice())
+    }
+}
+
+/// `Source` is a single `source` entry along with its checksums
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Source {
+    pub name: Option<String>, // File name given as `name::url` if any
+    pub location: String,     // URL or local file e.g. `foo.patch`
+    pub checksums: Vec<(String, String)>, // Checksums by array e.g. `sha256sums`
+}
+impl Source {
+    /// Name of the downloaded file e.g. `foo-2.1.0.tar.gz`
+    pub fn filename(&self) -> &str {
+        match self.name {
+            Some(ref name) => name,
+            None => self.location.rsplit('/').next().unwrap_or(&self.location),
+        }
+    }
+}
+
+impl SrcInfo {
+    /// Load a `.SRCINFO` from the given path
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path).map_err(|e| Error::SrcInfoLoad(path.to_owned(), e))?;
+        Self::try_from(data.as_str())
+    }
+
+    /// Full version shared by the split packages e.g. `1:2.1.0-3`
+    pub fn version(&self) -> Version {
+        let first = |key| self.base.get(key).and_then(|x| x.first());
+        let mut version = first("pkgver").cloned().unwrap_or_default();
+        if let Some(epoch) = first("epoch").filter(|x| !x.is_empty()) {
+            version = format!("{}:{}", epoch, version);
+        }
+        if let Some(pkgrel) = first("pkgrel") {
+            version = format!("{}-{}", version, pkgrel);
+        }
+        Version::from(version)
+    }
+
+    /// Names of the split packages
+    pub fn names(&self) -> Vec<&str> {
+        self.packages.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    /// Resolve the named split package for the given architecture e.g. `x86_64` by
+    /// applying its overrides to the `pkgbase` defaults
+    pub fn package(&self, name: &str, arch: &str) -> Result<Package> {
+        let section = self
+            .packages
+            .iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| Error::PackageNotFound(name.to_owned()))?;
+        let values = |key| self.values(section, key, arch);
+        let value = |key| values(key).first().cloned().unwrap_or_default();
+        let depends = |key| {
+            values(key)
+                .iter()
+                .map(|x| Dependency::from(x.as_str()))
+                .collect()
+        };
+
+        let arches = values("arch");
+
+        Ok(Package {
+            name: section.name.to_owned(),
+            base: self.base.name.to_owned(),
+            version: self.version(),
+            desc: value("pkgdesc"),
+            groups: values("groups"),
+            url: value("url"),
+            arch: if arches.iter().any(|x| x == "any") {
+                "any".to_owned()
+            } else {
+                arch.to_owned()
+            },
+            licenses: values("license"),
+            replaces: depends("replaces"),
+            conflicts: depends("conflicts"),
+            provides: depends("provides"),
+            depends: depends("depends"),
+            make_depends: depends("makedepends"),
+            optional_depends: values("optdepends")
+                .iter()
+                .map(|x| OptionalDependency::from(x.as_str()))
+                .collect(),
+            check_depends: depends("checkdepends"),
+            backup: values("backup")
+                .into_iter()
+                .map(|path| Backup {
+                    path,
+                    md5sum: Default::default(),
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Resolve the split packages that can be built for the given architecture
+    /// e.g. `x86_64` including those built for `any`
+    pub fn packages(&self, arch: &str) -> Vec<Package> {
+        self.packages
+            .iter()
+            .filter(|x| {
+                self.values(x, "arch", arch)
+                    .iter()
+                    .any(|x| x == arch || x == "any")
+            })
+            .filter_map(|x| self.package(&x.name, arch).ok())
+            .collect()
+    }
+
+    /// Sources for the given architecture e.g. `x86_64` with their checksums. The
+    /// architecture specific sources follow the common ones just as in makepkg.
+    pub fn sources(&self, arch: &str) -> Vec<Source> {
+        let arch_key = |key: &str| format!("{}_{}", key, arch);
+        let mut sources = Vec::new();
+        for suffix in &[None, Some(arch_key("source"))] {
+            let key = suffix.as_deref().unwrap_or("source");
+            for (i, x) in self.base.get(key).unwrap_or_default().iter().enumerate() {
+                let (name, location) = match x.split_once("::") {
+                    Some((name, location)) => (Some(name.to_owned()), location.to_owned()),
+                    None => (None, x.to_owned()),
+                };
+                let checksums = CHECKSUM_KEYS
+                    .iter()
+                    .filter_map(|kind| {
+                        let key = match suffix {
+                            Some(_) => arch_key(kind),
+                            None => kind.to_string(),
+                        };
+                        let sum = self.base.get(&key)?.get(i)?;
+                        Some((kind.to_string(), sum.to_owned()))
+                    })
+                    .collect();
+                sources.push(Source {
+                    name,
+                    location,
+                    checksums,
+                });
+            }
+        }
+        sources
+    }
+
+    /// Values of a property for a split package and architecture. The package
+    /// section overrides `pkgbase` and the architecture specific array is appended.
+    fn values(&self, section: &SrcInfoSection, key: &str, arch: &str) -> Vec<String> {
+        let lookup = |key: &str| {
+            section
+                .get(key)
+                .or_else(|| self.base.get(key))
+                .unwrap_or_default()
+                .to_vec()
+        };
+        let mut values = lookup(key);
+        values.extend(lookup(&format!("{}_{}", key, arch)));
+        values
+    }
+}
+
+impl TryFrom<&str> for SrcInfo {
+    type Error = Error;
+
+    /// Parse `.SRCINFO` text. An empty value e.g. `depends =` is kept as a property
+    /// without values so that a split package can clear the `pkgbase` default.
+    fn try_from(data: &str) -> Result<Self> {
+        let mut info = SrcInfo::default();
+        let mut section: Option<&mut SrcInfoSection> = None;
+
+        for x in assignments("", data) {
+            let x = x?;
+            let value = x.values[0];
+            match x.name {
+                "pkgbase" if section.is_none() => {
+                    info.base.name = value.to_owned();
+                    section = Some(&mut info.base);
+                }
+                "pkgname" if section.is_some() => {
+                    info.packages.push(SrcInfoSection {
+                        name: value.to_owned(),
+                        ..Default::default()
+                    });
+                    section = info.packages.last_mut();
+                }
+                _ => match section {
+                    Some(ref mut section) => {
+                        let values = if value.is_empty() {
+                            vec![]
+                        } else {
+                            vec![value]
+                        };
+                        push_extra(
+                            &mut section.properties,
+                            &Token {
+                                name: x.name,
+                                values,
+                            },
+                        )
+                    }
+                    None => {
+                        let err = Error::PackagePropertyMissing("pkgbase".to_owned());
+                        return Err(ValueError::new(x.name, err).locate_assignment("", data, &x));
+                    }
+                },
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let info = SrcInfo::load("tests/srcinfo/.SRCINFO").unwrap();
+        assert_eq!(info.base.name, "python-foo");
+        assert_eq!(info.names(), vec!["python-foo", "python-foo-docs"]);
+        assert_eq!(info.version(), Version::from("1:2.1.0-3"));
+
+        let pkg = info.package("python-foo", "x86_64").unwrap();
+        assert_eq!(pkg.base, "python-foo");
+        assert_eq!(pkg.desc, "A foo library");
+        assert_eq!(pkg.arch, "x86_64");
+        assert_eq!(pkg.licenses, vec!["MIT"]);
+        assert_eq!(pkg.provides, vec![Dependency::from("foo")]);
+        assert_eq!(
+            pkg.depends,
+            vec![Dependency::from("python"), Dependency::from("lib32-glibc")]
+        );
+        assert_eq!(
+            pkg.make_depends,
+            vec![
+                Dependency::from("python-setuptools"),
+                Dependency::from("nasm")
+            ]
+        );
+        assert_eq!(pkg.check_depends, vec![Dependency::from("python-pytest")]);
+
+        let pkg = info.package("python-foo", "aarch64").unwrap();
+        assert_eq!(pkg.depends, vec![Dependency::from("python")]);
+
+        let docs = info.package("python-foo-docs", "x86_64").unwrap();
+        assert_eq!(docs.desc, "Documentation for python-foo");
+        assert_eq!(docs.arch, "any");
+        assert!(docs.depends.is_empty());
+        assert!(docs.optional_depends.is_empty());
+        assert!(docs.provides.is_empty());
+        assert_eq!(docs.make_depends.len(), 2);
+
+        assert!(matches!(
+            info.package("python-bar", "x86_64"),
+            Err(Error::PackageNotFound(_))
+        ));
+        assert_eq!(info.packages("x86_64").len(), 2);
+        assert_eq!(info.packages("armv7h").len(), 1);
+    }
+
+    #[test]
+    fn test_sources() {
+        let info = SrcInfo::load("tests/srcinfo/.SRCINFO").unwrap();
+        let sources = info.sources("x86_64");
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].name, Some("foo-2.1.0.tar.gz".to_owned()));
+        assert_eq!(sources[0].location, "https://example.com/foo-2.1.0.tar.gz");
+        assert_eq!(
+            sources[1],
+            Source {
+                name: None,
+                location: "foo.patch".to_owned(),
+                checksums: vec![
+                    ("sha256sums".to_owned(), "SKIP".to_owned()),
+                    ("b2sums".to_owned(), "3c4d".to_owned())
+                ],
+            }
+        );
+
+        let sources = info.sources("aarch64");
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[2].filename(), "arm.patch");
+        assert_eq!(
+            sources[2].checksums,
+            vec![("sha256sums".to_owned(), "SKIP".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_resolve_unbuilt() {
+        let info = SrcInfo::load("tests/srcinfo/.SRCINFO").unwrap();
+        let repo = info
+            .packages("x86_64")
+            .into_iter()
+            .chain(vec![
+                Package {
+                    name: "python".to_owned(),
+                    ..Default::default()
+                },
+                Package {
+                    name: "lib32-glibc".to_owned(),
+                    ..Default::default()
+                },
+            ])
+            .collect::<RepoIndex>();
+        let repos = vec![repo.with_name("local")];
+        let resolved = Resolver::new(&repos).resolve(&["foo"]).unwrap();
+        let names = resolved
+            .iter()
+            .map(|x| x.package.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["python", "lib32-glibc", "python-foo"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            SrcInfo::try_from("\tpkgdesc = orphan\n"),
+            Err(Error::PackageParse(_))
+        ));
+        assert!(matches!(
+            SrcInfo::try_from("pkgbase = foo\n\tpkgdesc\n"),
+            Err(Error::PackageParse(_))
+        ));
+    }
+}
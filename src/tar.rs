@@ -0,0 +1,258 @@
+use ::tar::{EntryType, Header};
+use std::io::{self, Read};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Largest entry read into memory. Database entries are small text files so anything
+/// larger comes from a corrupt or hostile archive.
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+/// `TarEntry` is a single archive member read fully into memory unless filtered out
+#[derive(Debug, Clone)]
+pub(crate) struct TarEntry {
+    pub(crate) path: String,
+    pub(crate) kind: EntryType,
+    pub(crate) data: Vec<u8>,
+}
+
+/// `TarReader` streams entries from a tar archive it owns. Unlike `tar::Archive` the
+/// yielded entries don't borrow from the reader, which allows iterators built on
+/// top of it to own their whole state.
+pub(crate) struct TarReader<R: Read> {
+    inner: R,
+    done: bool,
+    keep: fn(&str) -> bool, // Entries by path whose data is read
+}
+
+impl<R: Read> TarReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            done: false,
+            keep: |_| true,
+        }
+    }
+
+    /// Only read the data of entries whose path is kept. The data of other entries is
+    /// skipped as it streams past and they are yielded without any.
+    pub(crate) fn with_filter(mut self, keep: fn(&str) -> bool) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Read the next header block, returning false at a clean end of stream
+    fn read_block(&mut self, block: &mut [u8; BLOCK_SIZE]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < BLOCK_SIZE {
+            match self.inner.read(&mut block[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Read the data for an entry of the given size including the block padding. The
+    /// buffer grows with the data actually read rather than the size in the header.
+    fn read_data(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        if size > MAX_ENTRY_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("tar entry of {} bytes is too large", size),
+            ));
+        }
+        let mut data = Vec::new();
+        (&mut self.inner).take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let padding = (BLOCK_SIZE - (size as usize % BLOCK_SIZE)) % BLOCK_SIZE;
+        io::copy(&mut (&mut self.inner).take(padding as u64), &mut io::sink())?;
+        Ok(data)
+    }
+
+    /// Skip the data for an entry of the given size including the block padding
+    fn skip_data(&mut self, size: u64) -> io::Result<()> {
+        let padded = size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64;
+        let skipped = io::copy(&mut (&mut self.inner).take(padded), &mut io::sink())?;
+        if skipped < padded {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    fn read_entry(&mut self) -> io::Result<Option<TarEntry>> {
+        let mut long_path: Option<String> = None;
+        loop {
+            let mut block = [0u8; BLOCK_SIZE];
+            if !self.read_block(&mut block)? || block.iter().all(|&x| x == 0) {
+                return Ok(None);
+            }
+            let header = Header::from_byte_slice(&block);
+            if header.cksum()? != checksum(&block) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "tar header checksum mismatch",
+                ));
+            }
+
+            let size = header.entry_size()?;
+            match header.entry_type() {
+                EntryType::XHeader => long_path = pax_path(&self.read_data(size)?).or(long_path),
+                EntryType::GNULongName => {
+                    let data = self.read_data(size)?;
+                    let name = data.split(|&x| x == 0).next().unwrap_or_default();
+                    long_path = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                EntryType::XGlobalHeader => self.skip_data(size)?,
+                kind => {
+                    let path = long_path.take().unwrap_or_else(|| {
+                        String::from_utf8_lossy(&header.path_bytes()).into_owned()
+                    });
+                    let data = if (self.keep)(&path) {
+                        self.read_data(size)?
+                    } else {
+                        self.skip_data(size)?;
+                        Vec::new()
+                    };
+                    return Ok(Some(TarEntry { path, kind, data }));
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for TarReader<R> {
+    type Item = io::Result<TarEntry>;
+
+    /// Iterate over the archive entries, stopping after the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_entry() {
+            Ok(Some(x)) => Some(Ok(x)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Compute the header checksum treating the checksum field itself as spaces
+fn checksum(block: &[u8; BLOCK_SIZE]) -> u32 {
+    block
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                x as u32
+            }
+        })
+        .sum()
+}
+
+/// Extract the `path` record from pax extended header data
+fn pax_path(data: &[u8]) -> Option<String> {
+    let data = String::from_utf8_lossy(data);
+    data.lines()
+        .filter_map(|x| x.split_once(' ').map(|(_, record)| record))
+        .filter_map(|x| x.split_once('='))
+        .find(|(key, _)| *key == "path")
+        .map(|(_, value)| value.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_read_entries() {
+        let f = File::open("tests/compression/none.db").unwrap();
+        let entries = TarReader::new(f).collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.len(), 466);
+        assert_eq!(entries[0].path, "acl-2.2.53-3/");
+        assert_eq!(entries[0].kind, EntryType::Directory);
+        assert_eq!(entries[1].path, "acl-2.2.53-3/desc");
+        assert_eq!(entries[1].kind, EntryType::Regular);
+        assert!(entries[1].data.starts_with(b"%FILENAME%\nacl-2.2.53-3"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let f = File::open("tests/compression/none.db").unwrap();
+        let entries = TarReader::new(f)
+            .with_filter(|x| x.starts_with("bash-"))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 466);
+        let kept = entries
+            .iter()
+            .filter(|x| !x.data.is_empty())
+            .map(|x| x.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec!["bash-5.1.004-1/desc"]);
+
+        // Skipping is still bounded by the data actually present
+        let mut header = Header::new_gnu();
+        header.set_path("huge/desc").unwrap();
+        header.set_size(1 << 40);
+        header.set_cksum();
+        let mut reader = TarReader::new(header.as_bytes().as_ref()).with_filter(|_| false);
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_pax_path() {
+        assert_eq!(
+            pax_path(b"30 mtime=1608489861.123456789\n20 path=foo/bar/desc\n"),
+            Some("foo/bar/desc".to_owned())
+        );
+        assert_eq!(pax_path(b"30 mtime=1608489861.123456789\n"), None);
+    }
+
+    #[test]
+    fn test_truncated_archive() {
+        let mut data = Vec::new();
+        File::open("tests/compression/none.db")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data.truncate(700);
+
+        let mut reader = TarReader::new(&data[..]);
+        assert_eq!(reader.next().unwrap().unwrap().path, "acl-2.2.53-3/");
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_oversized_entry() {
+        let mut header = Header::new_gnu();
+        header.set_path("huge/desc").unwrap();
+        header.set_size(1 << 40);
+        header.set_cksum();
+        let mut reader = TarReader::new(header.as_bytes().as_ref());
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // A size within bounds that the data doesn't back is a truncated archive
+        header.set_size(1 << 20);
+        header.set_cksum();
+        let mut reader = TarReader::new(header.as_bytes().as_ref());
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
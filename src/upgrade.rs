@@ -0,0 +1,268 @@
+use crate::prelude::*;
+use glob::Pattern;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// `Upgrades` computes the pending upgrades of the installed packages against the sync
+/// databases just like `pacman -Qu` or `checkupdates`. Nothing is written and no lock
+/// is taken so it can run without root.
+#[derive(Debug, Default, Clone)]
+pub struct Upgrades {
+    ignore_pkg: Vec<String>,   // Packages to hold back e.g. `linux*`
+    ignore_group: Vec<String>, // Groups to hold back e.g. `gnome`
+}
+impl Upgrades {
+    /// Set the ignored packages and groups from the `IgnorePkg` and `IgnoreGroup`
+    /// options of the config
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.ignore_pkg.extend(config.ignore_pkg.iter().cloned());
+        self.ignore_group
+            .extend(config.ignore_group.iter().cloned());
+        self
+    }
+
+    /// Hold back the given package, glob patterns are supported e.g. `linux*`
+    pub fn with_ignore_pkg(mut self, name: &str) -> Self {
+        self.ignore_pkg.push(name.to_owned());
+        self
+    }
+
+    /// Hold back the members of the given group e.g. `gnome`
+    pub fn with_ignore_group(mut self, name: &str) -> Self {
+        self.ignore_group.push(name.to_owned());
+        self
+    }
+
+    /// Load the installed packages and the sync databases and compare them
+    pub fn load(&self, local: &LocalDatabase, databases: &DatabaseSet) -> Result<UpgradeReport> {
+        let installed = local.packages()?.collect::<Result<Vec<_>>>()?;
+        let repos = databases.index()?;
+        Ok(self.check(installed.iter().map(|x| &x.package), &repos))
+    }
+
+    /// Compare the installed packages with the repositories in priority order e.g.
+    /// from `DatabaseSet::index`. A package is only upgraded from the first repository
+    /// that has it, even when a later repository has a newer version. Packages listed
+    /// in the `%REPLACES%` of a sync package are replaced from the first repository
+    /// with such a package instead.
+    pub fn check<'a>(
+        &self,
+        installed: impl IntoIterator<Item = &'a Package>,
+        repos: &[RepoIndex],
+    ) -> UpgradeReport {
+        let mut report = UpgradeReport::default();
+        for local in installed {
+            if let Some((repo, pkg)) = replacer(local, repos) {
+                let upgrade = Upgrade::new(local, repo, pkg);
+                if self.ignored(pkg) || self.ignored(local) {
+                    report.ignored.push(upgrade);
+                } else {
+                    report.upgrades.push(upgrade);
+                }
+                continue;
+            }
+
+            let found = repos
+                .iter()
+                .find_map(|repo| repo.get(&local.name).ok().map(|x| (repo.name(), x)));
+            let (repo, pkg) = match found {
+                Some(x) => x,
+                None => {
+                    report.foreign.push(local.name.to_owned());
+                    continue;
+                }
+            };
+            if pkg.version.vercmp(&local.version) != Ordering::Greater {
+                continue;
+            }
+            if self.ignored(pkg) {
+                report.ignored.push(Upgrade::new(local, repo, pkg));
+            } else {
+                report.upgrades.push(Upgrade::new(local, repo, pkg));
+            }
+        }
+        report
+    }
+
+    /// Check if the package or one of its groups is held back
+    fn ignored(&self, pkg: &Package) -> bool {
+        let matches = |x: &String, name: &str| match Pattern::new(x) {
+            Ok(pattern) => pattern.matches(name),
+            Err(_) => x == name,
+        };
+        self.ignore_pkg.iter().any(|x| matches(x, &pkg.name))
+            || pkg
+                .groups
+                .iter()
+                .any(|group| self.ignore_group.iter().any(|x| matches(x, group)))
+    }
+}
+
+/// Find the first repository with a package replacing the installed package. As with
+/// pacman the replacement must name the package itself, provisions don't count.
+fn replacer<'a>(local: &Package, repos: &'a [RepoIndex]) -> Option<(&'a str, &'a Package)> {
+    repos.iter().find_map(|repo| {
+        repo.iter()
+            .filter(|x| x.name != local.name)
+            .find(|x| {
+                x.replaces
+                    .iter()
+                    .any(|dep| dep.name == local.name && dep.matches(&local.version))
+            })
+            .map(|x| (repo.name(), x))
+    })
+}
+
+/// `UpgradeReport` is the outcome of comparing the installed packages with the sync
+/// databases. Each list is in the order of the installed packages given.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UpgradeReport {
+    pub upgrades: Vec<Upgrade>, // Pending upgrades and replacements
+    pub ignored: Vec<Upgrade>,  // Upgrades held back by `IgnorePkg` or `IgnoreGroup`
+    pub foreign: Vec<String>,   // Installed packages found in no repository
+}
+impl UpgradeReport {
+    /// Total size of the packages to download for the pending upgrades. A package
+    /// replacing several installed packages is only counted once.
+    pub fn download_size(&self) -> u64 {
+        let mut seen = HashSet::new();
+        self.upgrades
+            .iter()
+            .filter(|x| seen.insert((x.repo.as_str(), x.new_name())))
+            .map(|x| x.download_size)
+            .sum()
+    }
+}
+
+/// `Upgrade` is an installed package with a newer version or a replacement available
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Upgrade {
+    pub name: String,                // Name of the installed package
+    pub old_version: Version,        // Installed version
+    pub new_version: Version,        // Version in the sync database
+    pub repo: String,                // Repository of the new version e.g. `core`
+    pub download_size: u64,          // Size of the package file to download
+    pub replaced_by: Option<String>, // Name of the replacing package if any
+}
+impl Upgrade {
+    fn new(local: &Package, repo: &str, pkg: &Package) -> Self {
+        Self {
+            name: local.name.to_owned(),
+            old_version: local.version.to_owned(),
+            new_version: pkg.version.to_owned(),
+            repo: repo.to_owned(),
+            download_size: pkg.size,
+            replaced_by: Some(pkg.name.to_owned()).filter(|x| *x != local.name),
+        }
+    }
+
+    /// Name of the package to install e.g. the replacing package
+    pub fn new_name(&self) -> &str {
+        self.replaced_by.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{package, repo};
+
+    fn repos() -> Vec<RepoIndex> {
+        let mut gnome = package("gedit", "3.38-1", &[]);
+        gnome.groups = vec!["gnome".to_owned()];
+        let mut bar = package("bar", "2.0-1", &[]);
+        bar.replaces = vec![Dependency::from("oldbar<2")];
+        let mut testing = vec![package("foo", "1.2-1", &[]), package("baz", "0.9-1", &[])];
+        let mut core = vec![
+            package("foo", "1.1-1", &[]),
+            package("baz", "1.1-1", &[]),
+            package("linux", "5.10-1", &[]),
+            package("linux-lts", "5.4-1", &[]),
+            gnome,
+            bar,
+        ];
+        for x in testing.iter_mut().chain(core.iter_mut()) {
+            x.size = 100;
+        }
+        vec![repo("testing", testing), repo("core", core)]
+    }
+
+    #[test]
+    fn test_check() {
+        let installed = vec![
+            package("foo", "1.0-1", &[]),
+            package("baz", "1.0-1", &[]),
+            package("linux", "5.9-1", &[]),
+            package("linux-lts", "5.4-1", &[]),
+            package("gedit", "3.36-1", &[]),
+            package("oldbar", "1.0-1", &[]),
+            package("yay", "10.1.2-1", &[]),
+        ];
+        let report = Upgrades::default()
+            .with_ignore_pkg("linux*")
+            .with_ignore_group("gnome")
+            .check(&installed, &repos());
+
+        assert_eq!(
+            report.upgrades,
+            vec![
+                Upgrade {
+                    name: "foo".to_owned(),
+                    old_version: Version::from("1.0-1"),
+                    new_version: Version::from("1.2-1"),
+                    repo: "testing".to_owned(),
+                    download_size: 100,
+                    replaced_by: None,
+                },
+                Upgrade {
+                    name: "oldbar".to_owned(),
+                    old_version: Version::from("1.0-1"),
+                    new_version: Version::from("2.0-1"),
+                    repo: "core".to_owned(),
+                    download_size: 100,
+                    replaced_by: Some("bar".to_owned()),
+                },
+            ]
+        );
+        let ignored = report
+            .ignored
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ignored, vec!["linux", "gedit"]);
+        assert_eq!(report.foreign, vec!["yay"]);
+        assert_eq!(report.download_size(), 200);
+    }
+
+    #[test]
+    fn test_replaces_constraint() {
+        let installed = vec![package("oldbar", "2.1-1", &[])];
+        let report = Upgrades::default().check(&installed, &repos());
+        assert!(report.upgrades.is_empty());
+        assert_eq!(report.foreign, vec!["oldbar"]);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_load() {
+        let config = Config::load("tests/pacman.conf").unwrap();
+        let databases = DatabaseSet::default()
+            .with_database(Database::default().with_path("tests/sync/core.db"));
+        let local = LocalDatabase::default().with_dir("tests");
+        let report = Upgrades::default()
+            .with_config(&config)
+            .load(&local, &databases)
+            .unwrap();
+        assert_eq!(report.upgrades.len(), 1);
+        let x = &report.upgrades[0];
+        assert_eq!(x.name, "coreutils");
+        assert_eq!(x.old_version, "8.31-3");
+        assert_eq!(x.new_version, "8.32-1");
+        assert_eq!(x.repo, "core");
+        assert!(x.download_size > 0);
+        assert_eq!(report.download_size(), x.download_size);
+        assert_eq!(report.foreign, vec!["yay"]);
+    }
+}
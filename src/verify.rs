@@ -0,0 +1,272 @@
+use crate::prelude::*;
+use md5::{Digest, Md5};
+use rayon::prelude::*;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// `CacheVerifier` checks the package files in a cache directory against the size
+/// and checksums recorded in the sync databases
+#[derive(Debug, Clone)]
+pub struct CacheVerifier {
+    dir: PathBuf, // Directory of downloaded packages e.g. `/var/cache/pacman/pkg`
+}
+impl CacheVerifier {
+    /// Set the directory of downloaded packages to verify
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Verify the package files in the directory against the given packages matched
+    /// by `filename`. Files are hashed in parallel and a file that can't be read is
+    /// reported rather than failing the whole directory.
+    pub fn verify<'a>(
+        &self,
+        packages: impl IntoIterator<Item = &'a Package>,
+    ) -> Result<VerifyReport> {
+        let mut expected = packages
+            .into_iter()
+            .filter(|x| !x.filename.is_empty())
+            .map(|x| (x.filename.as_str(), x))
+            .collect::<HashMap<_, _>>();
+
+        let mut report = VerifyReport::default();
+        let mut found = Vec::new();
+        let load = |e| Error::CacheLoad(self.dir.to_owned(), e);
+        for entry in fs::read_dir(&self.dir).map_err(load)? {
+            let entry = entry.map_err(load)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_package_file(&name) {
+                continue;
+            }
+            match fs::metadata(entry.path()) {
+                Ok(x) if x.is_file() => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    expected.remove(name.as_str());
+                    report.unreadable.push((name, e.to_string()));
+                    continue;
+                }
+            }
+            match expected.remove(name.as_str()) {
+                Some(pkg) => found.push((entry.path(), pkg)),
+                None => report.extra.push(name),
+            }
+        }
+        report.missing = expected.keys().map(|x| x.to_string()).collect();
+
+        let results = found
+            .par_iter()
+            .map(|(path, pkg)| {
+                let digests = path.metadata().and_then(|x| Ok((x.len(), digests(path)?)));
+                (pkg, digests)
+            })
+            .collect::<Vec<_>>();
+
+        for (pkg, digests) in results {
+            let (size, (md5sum, sha256sum)) = match digests {
+                Ok(x) => x,
+                Err(e) => {
+                    report
+                        .unreadable
+                        .push((pkg.filename.to_owned(), e.to_string()));
+                    continue;
+                }
+            };
+            let mut mismatches = Vec::new();
+            if size != pkg.size {
+                mismatches.push(Mismatch::Size(pkg.size, size));
+            }
+            if !pkg.sha256sum.is_empty() && pkg.sha256sum != sha256sum {
+                mismatches.push(Mismatch::Sha256sum(pkg.sha256sum.to_owned(), sha256sum));
+            }
+            if !pkg.md5sum.is_empty() && pkg.md5sum != md5sum {
+                mismatches.push(Mismatch::Md5sum(pkg.md5sum.to_owned(), md5sum));
+            }
+            if mismatches.is_empty() {
+                report.verified.push(pkg.filename.to_owned());
+            } else {
+                report.mismatched.push(FileMismatch {
+                    filename: pkg.filename.to_owned(),
+                    mismatches,
+                });
+            }
+        }
+
+        report.verified.sort();
+        report
+            .mismatched
+            .sort_by(|x, y| x.filename.cmp(&y.filename));
+        report.missing.sort();
+        report.extra.sort();
+        report.unreadable.sort();
+        Ok(report)
+    }
+}
+
+/// Use `CacheVerifier::default()` and the builder pattern to call various `with_`
+/// options to control the exact verification desired.
+impl Default for CacheVerifier {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_CACHE_DIR),
+        }
+    }
+}
+
+/// `VerifyReport` is the outcome of verifying a cache directory. Each list is sorted
+/// by file name.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VerifyReport {
+    pub verified: Vec<String>,         // Files matching their database entry
+    pub mismatched: Vec<FileMismatch>, // Files differing from their database entry
+    pub missing: Vec<String>,          // Database entries without a file
+    pub extra: Vec<String>,            // Package files without a database entry
+    pub unreadable: Vec<(String, String)>, // Files that failed to read and the error
+}
+impl VerifyReport {
+    /// Check if every file read matched its database entry and none were extra.
+    /// Missing files are ignored as a cache normally only holds the packages that were
+    /// downloaded, not every package in the databases.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.extra.is_empty() && self.unreadable.is_empty()
+    }
+}
+
+/// `FileMismatch` is a package file that differs from its database entry
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileMismatch {
+    pub filename: String,          // File name of the package
+    pub mismatches: Vec<Mismatch>, // Each property that differs
+}
+
+/// `Mismatch` is a single property of a package file that differs from the database
+/// given as the expected value followed by the actual value
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Mismatch {
+    Size(u64, u64),
+    Sha256sum(String, String),
+    Md5sum(String, String),
+}
+
+/// Check if the file name is a package e.g. `acl-2.2.53-3-x86_64.pkg.tar.zst` rather
+/// than a signature or a partial download
+fn is_package_file(name: &str) -> bool {
+    name.contains(".pkg.tar") && !name.ends_with(".sig") && !name.ends_with(".part")
+}
+
+/// Hash the file at the given path returning its md5 and sha256 checksums as hex
+pub(crate) fn digests(path: &Path) -> io::Result<(String, String)> {
+    let mut f = File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match f.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                md5.update(&buf[..n]);
+                sha256.update(&buf[..n]);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((
+        format!("{:x}", md5.finalize()),
+        format!("{:x}", sha256.finalize()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixtures::foo;
+
+    const FOO: &str = "foo-1.0-1-x86_64.pkg.tar.zst";
+
+    /// The `foo` entry under another file name
+    fn renamed(filename: &str) -> Package {
+        Package {
+            name: filename.split('-').next().unwrap().to_owned(),
+            filename: filename.to_owned(),
+            ..foo()
+        }
+    }
+
+    #[test]
+    fn test_digests() {
+        let (md5sum, sha256sum) = digests(&Path::new("tests/pkg").join(FOO)).unwrap();
+        assert_eq!(md5sum, foo().md5sum);
+        assert_eq!(sha256sum, foo().sha256sum);
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = fs::read(Path::new("tests/pkg").join(FOO)).unwrap();
+        fs::write(dir.path().join(FOO), &data).unwrap();
+        fs::write(dir.path().join("bar-1.0-1-x86_64.pkg.tar.zst"), &data[1..]).unwrap();
+        fs::write(dir.path().join("baz-1.0-1-any.pkg.tar.zst"), &data).unwrap();
+        fs::write(dir.path().join("baz-1.0-1-any.pkg.tar.zst.sig"), b"sig").unwrap();
+        fs::write(dir.path().join("qux-1.0-1-any.pkg.tar.zst.part"), b"part").unwrap();
+
+        let mut bar = renamed("bar-1.0-1-x86_64.pkg.tar.zst");
+        bar.md5sum = Default::default();
+        let packages = vec![foo(), bar, renamed("missing-1.0-1-any.pkg.tar.zst")];
+
+        let report = CacheVerifier::default()
+            .with_dir(dir.path())
+            .verify(&packages)
+            .unwrap();
+        assert_eq!(report.verified, vec![FOO]);
+        assert_eq!(report.missing, vec!["missing-1.0-1-any.pkg.tar.zst"]);
+        assert_eq!(report.extra, vec!["baz-1.0-1-any.pkg.tar.zst"]);
+        assert_eq!(report.mismatched.len(), 1);
+        let x = &report.mismatched[0];
+        assert_eq!(x.filename, "bar-1.0-1-x86_64.pkg.tar.zst");
+        assert_eq!(x.mismatches.len(), 2);
+        assert_eq!(x.mismatches[0], Mismatch::Size(1708, 1707));
+        assert!(matches!(x.mismatches[1], Mismatch::Sha256sum(..)));
+        assert!(report.unreadable.is_empty());
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_verify_unreadable() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::copy(Path::new("tests/pkg").join(FOO), dir.path().join(FOO)).unwrap();
+        let gone = "gone-1.0-1-any.pkg.tar.zst";
+        std::os::unix::fs::symlink("vanished", dir.path().join(gone)).unwrap();
+
+        let packages = vec![foo(), renamed(gone)];
+        let report = CacheVerifier::default()
+            .with_dir(dir.path())
+            .verify(&packages)
+            .unwrap();
+        assert_eq!(report.verified, vec![FOO]);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unreadable.len(), 1);
+        assert_eq!(report.unreadable[0].0, gone);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_verify_missing_dir() {
+        let result = CacheVerifier::default()
+            .with_dir("tests/missing")
+            .verify(&[]);
+        assert!(matches!(result, Err(Error::CacheLoad(..))));
+    }
+}
@@ -0,0 +1,368 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// `Version` is a package version of the form `[epoch:]pkgver[-pkgrel]` e.g. `1:2.0-1`.
+/// Equality is on the original text as pacman's `strcmp`, so `1.01` and `1.1` differ.
+/// Ordering follows alpm's `vercmp` rules but is total: a missing pkgrel sorts before
+/// any pkgrel and versions `vercmp` considers equal are ordered by their text. Use
+/// `Version::vercmp` for pacman's comparison, which treats a missing pkgrel as a
+/// wildcard.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Version {
+    raw: String,
+}
+impl Version {
+    /// Version text exactly as given
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Epoch of the version defaulting to `0` when not given
+    pub fn epoch(&self) -> &str {
+        parse_evr(&self.raw).0
+    }
+
+    /// Upstream version e.g. `2.0` for `1:2.0-1`
+    pub fn pkgver(&self) -> &str {
+        parse_evr(&self.raw).1
+    }
+
+    /// Package release if given e.g. `1` for `1:2.0-1`
+    pub fn pkgrel(&self) -> Option<&str> {
+        parse_evr(&self.raw).2
+    }
+
+    /// Compare with another version using alpm's `vercmp` rules e.g. when checking
+    /// for upgrades or dependency constraints. Unlike `Ord` this is not a total order
+    /// as `1.5` is equal to both `1.5-1` and `1.5-2`.
+    pub fn vercmp(&self, other: &Version) -> Ordering {
+        vercmp(&self.raw, &other.raw)
+    }
+}
+
+impl From<&str> for Version {
+    fn from(data: &str) -> Self {
+        Self {
+            raw: data.to_owned(),
+        }
+    }
+}
+
+impl From<String> for Version {
+    fn from(data: String) -> Self {
+        Self { raw: data }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (epoch1, ver1, rel1) = parse_evr(&self.raw);
+        let (epoch2, ver2, rel2) = parse_evr(&other.raw);
+        rpmvercmp(epoch1, epoch2)
+            .then_with(|| rpmvercmp(ver1, ver2))
+            .then_with(|| match (rel1, rel2) {
+                (Some(rel1), Some(rel2)) => rpmvercmp(rel1, rel2),
+                (rel1, rel2) => rel1.is_some().cmp(&rel2.is_some()),
+            })
+            .then_with(|| self.raw.cmp(&other.raw))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<&str> for Version {
+    fn eq(&self, other: &&str) -> bool {
+        self.raw == *other
+    }
+}
+
+impl PartialOrd<&str> for Version {
+    fn partial_cmp(&self, other: &&str) -> Option<Ordering> {
+        Some(self.cmp(&Version::from(*other)))
+    }
+}
+
+/// Compare two version strings using alpm's `vercmp` rules. The pkgrel is only
+/// compared when both versions have one.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch1, ver1, rel1) = parse_evr(a);
+    let (epoch2, ver2, rel2) = parse_evr(b);
+
+    rpmvercmp(epoch1, epoch2)
+        .then_with(|| rpmvercmp(ver1, ver2))
+        .then_with(|| match (rel1, rel2) {
+            (Some(rel1), Some(rel2)) => rpmvercmp(rel1, rel2),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Split a version into epoch, pkgver and pkgrel as alpm's `parseEVR` does
+fn parse_evr(data: &str) -> (&str, &str, Option<&str>) {
+    let digits = data.bytes().take_while(|x| x.is_ascii_digit()).count();
+    let (epoch, rest) = match data[digits..].strip_prefix(':') {
+        Some(rest) if digits == 0 => ("0", rest),
+        Some(rest) => (&data[..digits], rest),
+        None => ("0", data),
+    };
+
+    // The release is split at the last dash after any epoch digits
+    let offset = data.len() - rest.len();
+    match data[digits.max(offset)..].rfind('-') {
+        Some(i) => {
+            let i = digits.max(offset) + i;
+            (epoch, &data[offset..i], Some(&data[i + 1..]))
+        }
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compare version segments as alpm's `rpmvercmp` does. Versions are split into runs
+/// of digits and letters with any other characters acting as separators.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (one, two) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < one.len() && j < two.len() {
+        let (start1, start2) = (i, j);
+        while i < one.len() && !one[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < two.len() && !two[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i >= one.len() || j >= two.len() {
+            break;
+        }
+
+        // Differing separator lengths decide the comparison e.g. `2___a` > `2_a`
+        if i - start1 != j - start2 {
+            return (i - start1).cmp(&(j - start2));
+        }
+
+        // Grab the next completely numeric or alpha segment from both
+        let numeric = one[i].is_ascii_digit();
+        let is_segment = |x: &u8| {
+            if numeric {
+                x.is_ascii_digit()
+            } else {
+                x.is_ascii_alphabetic()
+            }
+        };
+        let (seg1, seg2) = (i, j);
+        while i < one.len() && is_segment(&one[i]) {
+            i += 1;
+        }
+        while j < two.len() && is_segment(&two[j]) {
+            j += 1;
+        }
+        let (seg1, seg2) = (&one[seg1..i], &two[seg2..j]);
+
+        // Segments of different types, numeric segments are newer than alpha
+        if seg2.is_empty() {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let ordering = if numeric {
+            let seg1 = strip_zeros(seg1);
+            let seg2 = strip_zeros(seg2);
+            seg1.len().cmp(&seg2.len()).then_with(|| seg1.cmp(seg2))
+        } else {
+            seg1.cmp(seg2)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    // Whatever remains decides the comparison but a remaining alpha segment never
+    // beats an empty one e.g. `1.0rc` < `1.0` while `1.0.1` > `1.0`
+    let (rest1, rest2) = (&one[i..], &two[j..]);
+    let alpha = |x: &[u8]| x.first().is_some_and(|x| x.is_ascii_alphabetic());
+    if rest1.is_empty() && rest2.is_empty() {
+        Ordering::Equal
+    } else if (rest1.is_empty() && !alpha(rest2)) || alpha(rest1) {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+fn strip_zeros(data: &[u8]) -> &[u8] {
+    let zeros = data.iter().take_while(|&&x| x == b'0').count();
+    &data[zeros..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected results taken from pacman's `vercmptest.sh`
+    const VERCMP: &[(&str, &str, i8)] = &[
+        // all similar length, no pkgrel
+        ("1.5.0", "1.5.0", 0),
+        ("1.5.1", "1.5.0", 1),
+        // mixed length
+        ("1.5.1", "1.5", 1),
+        // with pkgrel, simple
+        ("1.5.0-1", "1.5.0-1", 0),
+        ("1.5.0-1", "1.5.0-2", -1),
+        ("1.5.0-1", "1.5.1-1", -1),
+        ("1.5.0-2", "1.5.1-1", -1),
+        // with pkgrel, mixed lengths
+        ("1.5-1", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-1", -1),
+        ("1.5-2", "1.5.1-2", -1),
+        // mixed pkgrel inclusion
+        ("1.5", "1.5-1", 0),
+        ("1.5-1", "1.5", 0),
+        ("1.1-1", "1.1", 0),
+        ("1.0-1", "1.1", -1),
+        ("1.1-1", "1.0", 1),
+        // alphanumeric versions
+        ("1.5b-1", "1.5-1", -1),
+        ("1.5b", "1.5", -1),
+        ("1.5b-1", "1.5", -1),
+        ("1.5b", "1.5.1", -1),
+        // from the manpage
+        ("1.0a", "1.0alpha", -1),
+        ("1.0alpha", "1.0b", -1),
+        ("1.0b", "1.0beta", -1),
+        ("1.0beta", "1.0rc", -1),
+        ("1.0rc", "1.0", -1),
+        // going crazy? alpha-dotted versions
+        ("1.5.a", "1.5", 1),
+        ("1.5.b", "1.5.a", 1),
+        ("1.5.1", "1.5.b", 1),
+        // alpha dots and dashes
+        ("1.5.b-1", "1.5.b", 0),
+        ("1.5-1", "1.5.b", -1),
+        // same/similar content, differing separators
+        ("2.0", "2_0", 0),
+        ("2.0_a", "2_0.a", 0),
+        ("2.0a", "2.0.a", -1),
+        ("2___a", "2_a", 1),
+        // epoch included version comparisons
+        ("0:1.0", "0:1.0", 0),
+        ("0:1.0", "0:1.1", -1),
+        ("1:1.0", "0:1.0", 1),
+        ("1:1.0", "0:1.1", 1),
+        ("1:1.0", "2:1.1", -1),
+        // epoch + sometimes present pkgrel
+        ("1:1.0", "0:1.0-1", 1),
+        ("1:1.0-1", "0:1.1-1", 1),
+        // epoch included on one version
+        ("0:1.0", "1.0", 0),
+        ("0:1.0", "1.1", -1),
+        ("0:1.1", "1.0", 1),
+        ("1:1.0", "1.0", 1),
+        ("1:1.0", "1.1", 1),
+        ("1:1.1", "1.1", 1),
+        // alpm has no tilde rule, it is a plain separator unlike rpm
+        ("1.0~rc1", "1.0", 1),
+        ("1.0~rc1", "1.0.rc1", 0),
+        // leading zeros are ignored in numeric segments
+        ("1.01", "1.1", 0),
+        ("1.010", "1.9", 1),
+    ];
+
+    #[test]
+    fn test_vercmp() {
+        for &(a, b, expected) in VERCMP {
+            let expected = expected.cmp(&0);
+            assert_eq!(vercmp(a, b), expected, "vercmp {} {}", a, b);
+            assert_eq!(vercmp(b, a), expected.reverse(), "vercmp {} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_parse_evr() {
+        assert_eq!(parse_evr("1:2.0-1"), ("1", "2.0", Some("1")));
+        assert_eq!(parse_evr("2.0-1"), ("0", "2.0", Some("1")));
+        assert_eq!(parse_evr("2.0"), ("0", "2.0", None));
+        assert_eq!(parse_evr(":2.0"), ("0", "2.0", None));
+        assert_eq!(
+            parse_evr("20201218.646f159-1"),
+            ("0", "20201218.646f159", Some("1"))
+        );
+        assert_eq!(
+            parse_evr("1:1.3_20201126-1"),
+            ("1", "1.3_20201126", Some("1"))
+        );
+        assert_eq!(parse_evr("1.0-rc1-2"), ("0", "1.0-rc1", Some("2")));
+    }
+
+    #[test]
+    fn test_version() {
+        let version = Version::from("1:2.0-1");
+        assert_eq!(version.epoch(), "1");
+        assert_eq!(version.pkgver(), "2.0");
+        assert_eq!(version.pkgrel(), Some("1"));
+        assert_eq!(version.to_string(), "1:2.0-1");
+        assert!(version > "1.9.9-3");
+        assert!(version < "2:0.1");
+        assert_eq!(Version::from("8.32-1"), "8.32-1");
+        assert_ne!(Version::from("1.01"), Version::from("1.1"));
+        assert_ne!(Version::from("1.0"), Version::from("0:1.0-1"));
+        assert_eq!(
+            Version::from("1.0").vercmp(&Version::from("0:1.0-1")),
+            Ordering::Equal
+        );
+
+        let mut versions = [
+            Version::from("1.0"),
+            Version::from("1:0.1"),
+            Version::from("1.0rc"),
+            Version::from("1.0.1"),
+        ];
+        versions.sort();
+        let sorted = versions.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        assert_eq!(sorted, vec!["1.0rc", "1.0", "1.0.1", "1:0.1"]);
+    }
+
+    #[test]
+    fn test_total_order() {
+        // `vercmp` equates each of these with its neighbours but the order is total
+        let mut versions = ["1.5-2", "1.5", "1.05-2", "1.5-1", "1.05", "1.5-10"]
+            .iter()
+            .map(|x| Version::from(*x))
+            .collect::<Vec<_>>();
+        versions.sort();
+        let sorted = versions.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            sorted,
+            vec!["1.05", "1.5", "1.5-1", "1.05-2", "1.5-2", "1.5-10"]
+        );
+        for x in &versions {
+            for y in &versions {
+                assert_eq!(x.cmp(y) == Ordering::Equal, x == y);
+                for z in &versions {
+                    if x < y && y < z {
+                        assert!(x < z, "{} < {} < {}", x, y, z);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,618 @@
+use crate::compression::compress;
+use crate::package::strings;
+use crate::prelude::*;
+use ::tar::{Builder, EntryType, Header};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// `DatabaseWriter` builds the `<repo>.db` and `<repo>.files` archives of a repository
+/// from packages just like `repo-add` and `repo-remove` do.
+///
+/// ### Examples
+/// ```no_run
+/// use package_iter::prelude::*;
+///
+/// let mut writer = DatabaseWriter::default()
+///     .with_dir("/srv/repo/x86_64")
+///     .with_name("custom")
+///     .with_prevent_downgrade(true)
+///     .load()
+///     .unwrap();
+/// writer.remove("foo").unwrap();
+/// writer.write().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatabaseWriter {
+    dir: PathBuf,                        // Repository directory e.g. `/srv/repo/x86_64`
+    name: String,                        // Name of the repository e.g. `custom`
+    compression: Compression,            // Codec of the archives, gzip like `repo-add`
+    new: bool,                           // Skip packages already present like `--new`
+    prevent_downgrade: bool,             // Refuse older versions like `--prevent-downgrade`
+    remove: bool,                        // Delete replaced package files like `--remove`
+    symlinks: bool,                      // Write `<repo>.db.tar.gz` and link `<repo>.db` to it
+    packages: BTreeMap<String, Package>, // Packages by name
+    replaced: Vec<String>,               // File names of replaced packages to delete
+    skipped: Vec<String>,                // Packages skipped by `--new`
+}
+impl DatabaseWriter {
+    /// Set the directory the repository lives in
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Set the name of the repository e.g. `custom`
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Set the codec used for the archives
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Skip packages already in the database with a warning like `repo-add --new`
+    pub fn with_new(mut self, new: bool) -> Self {
+        self.new = new;
+        self
+    }
+
+    /// Refuse to replace a package with an older version like
+    /// `repo-add --prevent-downgrade`
+    pub fn with_prevent_downgrade(mut self, prevent_downgrade: bool) -> Self {
+        self.prevent_downgrade = prevent_downgrade;
+        self
+    }
+
+    /// Delete the old package file and its signature from the repository directory
+    /// once a replaced entry is written like `repo-add --remove`
+    pub fn with_remove(mut self, remove: bool) -> Self {
+        self.remove = remove;
+        self
+    }
+
+    /// Write the archives as e.g. `custom.db.tar.gz` with a `custom.db` symlink
+    /// pointing at it, as `repo-add` does, rather than directly to `custom.db`
+    pub fn with_symlinks(mut self, symlinks: bool) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Load the packages of the existing database if there is one. The `.files`
+    /// database is preferred so that package file lists are kept.
+    pub fn load(mut self) -> Result<Self> {
+        let path = [self.link_path("files"), self.link_path("db")]
+            .iter()
+            .find(|x| x.is_file())
+            .cloned();
+        if let Some(path) = path {
+            for pkg in Database::default().with_path(path).packages()? {
+                let pkg = pkg?;
+                self.packages.insert(pkg.name.to_owned(), pkg);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Add a package replacing any existing package of the same name, subject to the
+    /// `--new`, `--prevent-downgrade` and `--remove` options
+    pub fn add(&mut self, pkg: Package) -> Result<()> {
+        if let Some(existing) = self.packages.get(&pkg.name) {
+            if self.new {
+                self.skipped.push(pkg.name);
+                return Ok(());
+            }
+            if self.prevent_downgrade && pkg.version.vercmp(&existing.version) == Ordering::Less {
+                let existing = existing.version.to_owned();
+                return Err(Error::PackageDowngrade(pkg.name, existing, pkg.version));
+            }
+        }
+        if let Some(existing) = self.packages.insert(pkg.name.to_owned(), pkg) {
+            if self.remove && !existing.filename.is_empty() {
+                self.replaced.push(existing.filename);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a package by name like `repo-remove`
+    pub fn remove(&mut self, name: &str) -> Result<Package> {
+        self.packages
+            .remove(name)
+            .ok_or_else(|| Error::PackageNotFound(name.to_owned()))
+    }
+
+    /// Get a package by name
+    pub fn get(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    /// Iterate over the packages in name order
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages.values()
+    }
+
+    /// Names of the packages skipped by `--new` as the database already had them,
+    /// which `repo-add` reports as warnings
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Write the `.db` and `.files` archives. Both archives are written to temporary
+    /// files in the same directory before either is renamed into place, and the
+    /// directory is synced after the renames, so readers never see a partially
+    /// written database. Replaced package files are deleted afterwards with `--remove`.
+    pub fn write(&mut self) -> Result<()> {
+        let mut staged = Vec::new();
+        let result = self.stage(&mut staged).and_then(|_| {
+            for (tmp, path) in &staged {
+                fs::rename(tmp, path).map_err(|e| Error::DatabaseWrite(path.to_owned(), e))?;
+            }
+            sync_dir(&self.dir).map_err(|e| Error::DatabaseWrite(self.dir.to_owned(), e))
+        });
+        if result.is_err() {
+            for (tmp, _) in &staged {
+                let _ = fs::remove_file(tmp);
+            }
+        }
+        result?;
+
+        for filename in self.replaced.drain(..) {
+            // A package may have been re-added with the same file name since
+            if self.packages.values().any(|x| x.filename == filename) {
+                continue;
+            }
+            for name in &[filename.to_owned(), format!("{}.sig", filename)] {
+                let path = self.dir.join(name);
+                match fs::remove_file(&path) {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                    x => x.map_err(|e| Error::DatabaseWrite(path, e))?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write each archive and symlink to a temporary sibling, recording the temporary
+    /// and final paths in the order they are to be renamed
+    fn stage(&self, staged: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+        for &kind in &["db", "files"] {
+            let path = self.archive_path(kind);
+            let write = |e| Error::DatabaseWrite(path.to_owned(), e);
+            let data = compress(&self.archive(kind == "files")?, self.compression, write)?;
+            staged.push(stage(&path, |tmp| {
+                fs::write(tmp, &data).and_then(|_| File::open(tmp)?.sync_all())
+            })?);
+
+            if self.symlinks {
+                let target = PathBuf::from(path.file_name().unwrap_or_default());
+                staged.push(stage(&self.link_path(kind), |tmp| symlink(&target, tmp))?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the uncompressed tar archive with a directory per package
+    fn archive(&self, files: bool) -> Result<Vec<u8>> {
+        let mut builder = Builder::new(Vec::new());
+        for pkg in self.packages.values() {
+            let dir = format!("{}-{}", pkg.name, pkg.version);
+            let mtime = pkg.build_date.parse::<u64>().unwrap_or_default();
+            let mut entries = vec![("desc", pkg.to_desc())];
+            if files {
+                entries.push(("files", pkg.to_files()));
+            }
+
+            let mut dir_header = header(EntryType::Directory, 0o755, 0, mtime);
+            append(&mut builder, &mut dir_header, &format!("{}/", dir), &[])?;
+            for (name, data) in entries {
+                let mut header = header(EntryType::Regular, 0o644, data.len() as u64, mtime);
+                append(
+                    &mut builder,
+                    &mut header,
+                    &format!("{}/{}", dir, name),
+                    data.as_bytes(),
+                )?;
+            }
+        }
+        builder
+            .into_inner()
+            .map_err(|e| Error::DatabaseWrite(self.dir.to_owned(), e))
+    }
+
+    /// Path of the archive e.g. `custom.db` or `custom.db.tar.gz` with symlinks
+    fn archive_path(&self, kind: &str) -> PathBuf {
+        if !self.symlinks {
+            return self.link_path(kind);
+        }
+        let ext = match self.compression.extension() {
+            "" => String::new(),
+            x => format!(".{}", x),
+        };
+        self.dir.join(format!("{}.{}.tar{}", self.name, kind, ext))
+    }
+
+    /// Path pacman downloads e.g. `custom.db`
+    fn link_path(&self, kind: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.name, kind))
+    }
+}
+
+/// Use `DatabaseWriter::default()` and the builder pattern to call various `with_`
+/// options before loading and writing the database.
+impl Default for DatabaseWriter {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("."),
+            name: Default::default(),
+            compression: Compression::Gzip,
+            new: false,
+            prevent_downgrade: false,
+            remove: false,
+            symlinks: false,
+            packages: Default::default(),
+            replaced: Default::default(),
+            skipped: Default::default(),
+        }
+    }
+}
+
+fn header(kind: EntryType, mode: u32, size: u64, mtime: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+fn append(
+    builder: &mut Builder<Vec<u8>>,
+    header: &mut Header,
+    path: &str,
+    data: &[u8],
+) -> Result<()> {
+    builder
+        .append_data(header, path, data)
+        .map_err(|e| Error::DatabaseWrite(PathBuf::from(path), e))
+}
+
+/// Create the file as a temporary sibling of the given path to be renamed over it,
+/// returning the temporary and final paths
+fn stage(path: &Path, create: impl FnOnce(&Path) -> io::Result<()>) -> Result<(PathBuf, PathBuf)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+    let _ = fs::remove_file(&tmp);
+    match create(&tmp) {
+        Ok(()) => Ok((tmp, path.to_owned())),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(Error::DatabaseWrite(path.to_owned(), e))
+        }
+    }
+}
+
+/// Sync the directory so that the renames within it are durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened for syncing on other platforms
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Platforms without symlinks get a copy of the archive instead
+#[cfg(not(unix))]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    fs::copy(link.with_file_name(target), link).map(|_| ())
+}
+
+impl Package {
+    /// Serialize the package into canonical `desc` text as `repo-add` writes it.
+    /// Fields come out in `repo-add` order with empty fields omitted and any unknown
+    /// keys kept at the end in their original order.
+    pub fn to_desc(&self) -> String {
+        let single = |x: &str| match x {
+            "" => vec![],
+            _ => vec![x.to_owned()],
+        };
+        let fields = vec![
+            ("FILENAME", single(&self.filename)),
+            ("NAME", single(&self.name)),
+            ("BASE", single(&self.base)),
+            ("VERSION", single(self.version.as_str())),
+            ("DESC", single(&self.desc)),
+            ("GROUPS", self.groups.to_owned()),
+            ("CSIZE", vec![self.size.to_string()]),
+            ("ISIZE", vec![self.isize.to_string()]),
+            ("MD5SUM", single(&self.md5sum)),
+            ("SHA256SUM", single(&self.sha256sum)),
+            ("PGPSIG", single(&self.pgpsig)),
+            ("URL", single(&self.url)),
+            ("LICENSE", self.licenses.to_owned()),
+            ("ARCH", single(&self.arch)),
+            ("BUILDDATE", single(&self.build_date)),
+            ("PACKAGER", single(&self.packager)),
+            ("REPLACES", strings(&self.replaces)),
+            ("CONFLICTS", strings(&self.conflicts)),
+            ("PROVIDES", strings(&self.provides)),
+            ("DEPENDS", strings(&self.depends)),
+            ("OPTDEPENDS", strings(&self.optional_depends)),
+            ("MAKEDEPENDS", strings(&self.make_depends)),
+            ("CHECKDEPENDS", strings(&self.check_depends)),
+        ];
+        let extra = self.extra.iter().map(|(k, v)| (k.as_str(), v.to_owned()));
+
+        let mut desc = String::new();
+        for (key, values) in fields.into_iter().chain(extra) {
+            write_field(&mut desc, key, &values);
+        }
+        desc
+    }
+
+    /// Write the canonical `desc` text to the given writer
+    pub fn write_desc(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(self.to_desc().as_bytes())
+    }
+
+    /// Serialize the file list into the `files` entry of a `.files` database
+    pub fn to_files(&self) -> String {
+        let mut data = String::new();
+        let files = self
+            .files
+            .iter()
+            .map(|x| x.path.to_owned())
+            .collect::<Vec<_>>();
+        write_field(&mut data, "FILES", &files);
+        data
+    }
+}
+
+/// Write a single `%KEY%` section followed by its values and a blank line
+fn write_field(data: &mut String, key: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+    data.push('%');
+    data.push_str(key);
+    data.push_str("%\n");
+    for x in values {
+        data.push_str(x);
+        data.push('\n');
+    }
+    data.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_to_desc() {
+        let pkg = Package {
+            name: "foo".to_owned(),
+            version: Version::from("1.0-1"),
+            size: 10,
+            depends: vec![Dependency::from("glibc>=2.33"), Dependency::from("bar")],
+            optional_depends: vec![OptionalDependency::from("baz: for baz support")],
+            extra: vec![("XDATA".to_owned(), vec!["pkgtype=pkg".to_owned()])],
+            ..Default::default()
+        };
+        assert_eq!(
+            pkg.to_desc(),
+            "%NAME%\nfoo\n\n%VERSION%\n1.0-1\n\n%CSIZE%\n10\n\n%ISIZE%\n0\n\n\
+             %DEPENDS%\nglibc>=2.33\nbar\n\n%OPTDEPENDS%\nbaz: for baz support\n\n\
+             %XDATA%\npkgtype=pkg\n\n"
+        );
+        assert_eq!(Package::try_from(pkg.to_desc().as_str()).unwrap(), pkg);
+
+        let mut data = Vec::new();
+        pkg.write_desc(&mut data).unwrap();
+        assert_eq!(data, pkg.to_desc().as_bytes());
+    }
+
+    #[test]
+    fn test_to_files() {
+        let pkg = Package {
+            files: vec![FileEntry::new("usr/"), FileEntry::new("usr/bin/foo")],
+            ..Default::default()
+        };
+        assert_eq!(pkg.to_files(), "%FILES%\nusr/\nusr/bin/foo\n\n");
+        assert_eq!(Package::default().to_files(), "");
+    }
+
+    #[cfg(feature = "gzip")]
+    fn core() -> Vec<Package> {
+        let db = Database::default().with_path("tests/sync/core.files");
+        db.packages().unwrap().map(|x| x.unwrap()).collect()
+    }
+
+    #[cfg(feature = "gzip")]
+    fn read(path: impl Into<PathBuf>) -> Vec<Package> {
+        let db = Database::default().with_path(path);
+        db.packages().unwrap().map(|x| x.unwrap()).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_write_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = DatabaseWriter::default()
+            .with_dir(dir.path())
+            .with_name("custom");
+        let mut pkgs = core();
+        for pkg in pkgs.iter().cloned() {
+            writer.add(pkg).unwrap();
+        }
+        writer.write().unwrap();
+        pkgs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut names = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["custom.db", "custom.files"]);
+
+        let files = read(dir.path().join("custom.files"));
+        assert_eq!(files, pkgs);
+        let db = read(dir.path().join("custom.db"));
+        assert_eq!(db.len(), pkgs.len());
+        assert!(db.iter().all(|x| x.files.is_empty()));
+        assert_eq!(db[0].to_desc(), pkgs[0].to_desc());
+    }
+
+    #[test]
+    #[cfg(all(feature = "gzip", feature = "zstd", feature = "xz"))]
+    fn test_write_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        for &compression in &[Compression::Zstd, Compression::Xz, Compression::Gzip] {
+            let mut writer = DatabaseWriter::default()
+                .with_dir(dir.path())
+                .with_name("custom")
+                .with_compression(compression)
+                .with_symlinks(true);
+            writer.add(core().remove(0)).unwrap();
+            writer.write().unwrap();
+
+            let archive = format!("custom.db.tar.{}", compression.extension());
+            let link = dir.path().join("custom.db");
+            assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from(&archive));
+            let data = fs::read(dir.path().join(&archive)).unwrap();
+            assert_eq!(Compression::detect(&data), Some(compression));
+            assert_eq!(read(&link).len(), 1);
+        }
+        assert!(fs::read_dir(dir.path()).unwrap().all(|x| !x
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_add_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkgs = core();
+        let mut writer = DatabaseWriter::default()
+            .with_dir(dir.path())
+            .with_name("custom");
+        writer.add(pkgs[0].to_owned()).unwrap();
+        writer.add(pkgs[1].to_owned()).unwrap();
+        writer.write().unwrap();
+
+        // Loading picks up the existing packages including file lists
+        let mut writer = DatabaseWriter::default()
+            .with_dir(dir.path())
+            .with_name("custom")
+            .with_new(true)
+            .load()
+            .unwrap();
+        assert_eq!(writer.packages().count(), 2);
+        assert_eq!(writer.get(&pkgs[0].name).unwrap(), &pkgs[0]);
+        let mut changed = pkgs[0].to_owned();
+        changed.desc = "changed".to_owned();
+        writer.add(changed).unwrap();
+        assert_eq!(writer.get(&pkgs[0].name).unwrap(), &pkgs[0]);
+        assert_eq!(writer.skipped(), &[pkgs[0].name.to_owned()]);
+        assert_eq!(writer.remove(&pkgs[1].name).unwrap(), pkgs[1]);
+        assert!(matches!(
+            writer.remove(&pkgs[1].name),
+            Err(Error::PackageNotFound(_))
+        ));
+        writer.write().unwrap();
+        assert_eq!(read(dir.path().join("custom.db")).len(), 1);
+
+        // Downgrades are refused only when asked to
+        let mut older = pkgs[0].to_owned();
+        older.version = Version::from("0.1-1");
+        let mut writer = DatabaseWriter::default()
+            .with_dir(dir.path())
+            .with_name("custom")
+            .with_prevent_downgrade(true)
+            .load()
+            .unwrap();
+        assert!(matches!(
+            writer.add(older.to_owned()),
+            Err(Error::PackageDowngrade(ref x, _, _)) if x == &older.name
+        ));
+        let mut newer = pkgs[0].to_owned();
+        newer.version = Version::from("99-1");
+        writer.add(newer).unwrap();
+        assert_eq!(writer.get(&pkgs[0].name).unwrap().version, "99-1");
+
+        let mut writer = writer.with_prevent_downgrade(false);
+        writer.add(older).unwrap();
+        assert_eq!(writer.get(&pkgs[0].name).unwrap().version, "0.1-1");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_remove_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pkg = core().remove(0);
+        let old = pkg.filename.to_owned();
+        for name in &[old.to_owned(), format!("{}.sig", old)] {
+            fs::write(dir.path().join(name), b"old").unwrap();
+        }
+        let mut writer = DatabaseWriter::default()
+            .with_dir(dir.path())
+            .with_name("custom")
+            .with_remove(true);
+        writer.add(pkg.to_owned()).unwrap();
+        writer.add(pkg.to_owned()).unwrap();
+        writer.write().unwrap();
+        assert!(dir.path().join(&old).is_file());
+
+        pkg.filename = "new.pkg.tar.zst".to_owned();
+        writer.add(pkg).unwrap();
+        assert!(dir.path().join(&old).is_file());
+        writer.write().unwrap();
+        assert!(!dir.path().join(&old).exists());
+        assert!(!dir.path().join(format!("{}.sig", old)).exists());
+        assert_eq!(
+            read(dir.path().join("custom.db"))[0].filename,
+            "new.pkg.tar.zst"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_round_trip_core_db() {
+        use crate::compression::decoder;
+        use crate::tar::TarReader;
+        use std::fs::File;
+
+        let f = File::open("tests/sync/core.db").unwrap();
+        let descs =
+            TarReader::new(decoder(f, |e| Error::DatabaseLoad("core.db".into(), e)).unwrap())
+                .map(|x| x.unwrap())
+                .filter(|x| x.path.ends_with("/desc"))
+                .map(|x| String::from_utf8(x.data).unwrap())
+                .collect::<Vec<_>>();
+        assert_eq!(descs.len(), 233);
+
+        let db = Database::default().with_path("tests/sync/core.db");
+        let pkgs = db.packages().unwrap().map(|x| x.unwrap());
+        for (desc, pkg) in descs.iter().zip(pkgs) {
+            assert_eq!(&pkg.to_desc(), desc, "{}", pkg.name);
+            assert_eq!(Package::try_from(desc.as_str()).unwrap(), pkg);
+        }
+    }
+}